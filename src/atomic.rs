@@ -0,0 +1,112 @@
+//! Whole-file rewrites that readers never observe half done.
+
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::IoContext;
+use crate::filesystem::{FileSystem, RealFileSystem};
+
+/// Where a new version of `dest` is written before it's renamed over it.
+fn temp_path(dest: &Path) -> PathBuf {
+    let mut tmp = dest.as_os_str().to_owned();
+    tmp.push(".tmp");
+    PathBuf::from(tmp)
+}
+
+/// A file written next to `dest` under a temporary name and renamed over
+/// it on `commit`. Dropping it without committing leaves `dest` untouched.
+pub(crate) struct AtomicFile {
+    tmp: PathBuf,
+    dest: PathBuf,
+    file: Option<BufWriter<fs::File>>,
+}
+
+impl AtomicFile {
+    pub(crate) fn create(dest: &Path) -> io::Result<AtomicFile> {
+        let tmp = temp_path(dest);
+        let file = BufWriter::new(fs::File::create(&tmp)?);
+
+        Ok(AtomicFile {
+            tmp,
+            dest: dest.to_path_buf(),
+            file: Some(file),
+        })
+    }
+
+    /// Renames the file over `dest` once it's durable, or removes it again
+    /// if either step fails.
+    pub(crate) fn commit(mut self) -> Result<(), Box<dyn Error>> {
+        let result = match self.file.take() {
+            Some(file) => file.into_inner().map_err(|e| e.into_error()).and_then(|file| file.sync_all()),
+            None => Ok(()),
+        };
+        if let Err(e) = result.and_then(|()| fs::rename(&self.tmp, &self.dest)) {
+            let _ = fs::remove_file(&self.tmp);
+            return Err(Box::new(e));
+        }
+        Ok(())
+    }
+}
+
+impl Write for AtomicFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file.as_mut().expect("written after commit").write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().expect("flushed after commit").flush()
+    }
+}
+
+impl Drop for AtomicFile {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.tmp);
+        }
+    }
+}
+
+/// Replaces the contents of `path` in one step.
+pub(crate) fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), Box<dyn Error>> {
+    Ok(replace_through(&RealFileSystem, path, contents)?)
+}
+
+/// `write_atomically` through `file_system`: `contents` go to `<path>.tmp`,
+/// which is then renamed over `path`, or removed again if either step fails.
+pub(crate) fn replace_through(file_system: &dyn FileSystem, path: &Path, contents: &[u8]) -> Result<(), IoContext> {
+    let tmp = temp_path(path);
+    if let Err(e) = file_system.write(&tmp, contents) {
+        let _ = file_system.remove(&tmp);
+        return Err(IoContext::wrap("write", &tmp)(e));
+    }
+    if let Err(e) = file_system.rename(&tmp, path) {
+        let _ = file_system.remove(&tmp);
+        return Err(IoContext::wrap("rename a new version over", path)(e));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempLogDir;
+
+    #[test]
+    fn failed_commits_leave_no_temp_file() {
+        let dir = TempLogDir::new();
+        let dest = &PathBuf::from(dir.file("atomic-commit-test/occupied"));
+        fs::create_dir_all(dest).unwrap();
+        fs::write(dest.join("keep"), b"keep").unwrap();
+
+        let mut file = AtomicFile::create(dest).unwrap();
+        file.write_all(b"new").unwrap();
+        assert!(file.commit().is_err());
+        assert!(!temp_path(dest).exists());
+
+        assert!(write_atomically(dest, b"new").is_err());
+        assert!(!temp_path(dest).exists());
+        assert_eq!(b"keep".as_slice(), fs::read(dest.join("keep")).unwrap());
+    }
+}
@@ -0,0 +1,198 @@
+//! Calendar math between `UNIX_EPOCH` timestamps or day counts and UTC
+//! dates.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str;
+
+const SECS_PER_DAY: u64 = 86_400;
+
+/// A UTC calendar date.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Date {
+    pub year: i64,
+    pub month: u32,
+    pub day: u32,
+}
+
+impl Date {
+    /// The UTC date that `secs` seconds after `UNIX_EPOCH` falls on.
+    pub fn from_timestamp(secs: u64) -> Date {
+        let (year, month, day) = civil_from_days((secs / SECS_PER_DAY) as i64);
+        Date { year, month, day }
+    }
+}
+
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// `secs` after `UNIX_EPOCH` as `YYYY-MM-DD HH:MM:SS` in UTC.
+pub(crate) fn format_datetime(secs: u64) -> String {
+    let time = secs % SECS_PER_DAY;
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        Date::from_timestamp(secs),
+        time / 3_600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// A timestamp formatted onto the stack rather than into a `String`. 40
+/// bytes are enough for any `u64` of seconds and `u16` of millis: the year
+/// has at most 12 digits and `-MM-DDTHH:MM:SS.mmmmmZ` takes 22 more.
+#[derive(Clone, Copy)]
+pub(crate) struct FixedTimestamp {
+    bytes: [u8; 40],
+    len: usize,
+}
+
+impl FixedTimestamp {
+    fn new(args: fmt::Arguments) -> FixedTimestamp {
+        let mut bytes = [0; 40];
+        let mut cursor = io::Cursor::new(&mut bytes[..]);
+        cursor.write_fmt(args).expect("a timestamp fits in 40 bytes");
+        let len = cursor.position() as usize;
+        FixedTimestamp { bytes, len }
+    }
+
+    pub(crate) fn as_str(&self) -> &str {
+        str::from_utf8(&self.bytes[..self.len]).expect("timestamps are ASCII")
+    }
+}
+
+impl fmt::Display for FixedTimestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// `format_datetime` without the allocation.
+pub(crate) fn datetime_fixed(secs: u64) -> FixedTimestamp {
+    let time = secs % SECS_PER_DAY;
+    FixedTimestamp::new(format_args!(
+        "{} {:02}:{:02}:{:02}",
+        Date::from_timestamp(secs),
+        time / 3_600,
+        time / 60 % 60,
+        time % 60
+    ))
+}
+
+/// `secs` after `UNIX_EPOCH` as an RFC 3339 UTC timestamp like
+/// `2024-03-07T14:09:03Z`, with `.mmm` before the `Z` given `millis`.
+pub(crate) fn rfc3339_fixed(secs: u64, millis: Option<u16>) -> FixedTimestamp {
+    let time = secs % SECS_PER_DAY;
+    let (h, m, s) = (time / 3_600, time / 60 % 60, time % 60);
+    let date = Date::from_timestamp(secs);
+    match millis {
+        Some(millis) => FixedTimestamp::new(format_args!("{}T{:02}:{:02}:{:02}.{:03}Z", date, h, m, s, millis)),
+        None => FixedTimestamp::new(format_args!("{}T{:02}:{:02}:{:02}Z", date, h, m, s)),
+    }
+}
+
+/// Howard Hinnant's `civil_from_days`: days since 1970-01-01 to
+/// `(year, month, day)` in the proleptic Gregorian calendar. Negative days
+/// are before the epoch. Never panics: within 719,468 days of `i64::MAX`
+/// the arithmetic wraps and the result is meaningless.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days.wrapping_add(719_468);
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+/// Howard Hinnant's `days_from_civil`, the inverse of `civil_from_days`.
+/// `month` is 1 to 12 and `day` 1 to 31; other values, and years too large
+/// to count in days, give a meaningless result rather than an error or a
+/// panic.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = year.wrapping_sub(i64::from(month <= 2));
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from(month.wrapping_add(9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+    era.wrapping_mul(146_097).wrapping_add(doe).wrapping_sub(719_468)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_from_timestamps() {
+        assert_eq!("1970-01-01", Date::from_timestamp(0).to_string());
+        assert_eq!("2000-02-29", Date::from_timestamp(951_782_400).to_string());
+        assert_eq!("2023-08-02", Date::from_timestamp(1_690_999_200).to_string());
+        assert_eq!("2024-12-31", Date::from_timestamp(1_735_689_599).to_string());
+    }
+
+    #[test]
+    fn december_dates() {
+        assert_eq!("2023-12-01", Date::from_timestamp(1_701_388_800).to_string());
+        assert_eq!("2023-12-15", Date::from_timestamp(1_702_598_400).to_string());
+        assert_eq!("2023-12-31", Date::from_timestamp(1_703_980_800).to_string());
+        // leap year, so each December day-of-year is one later
+        assert_eq!("2024-12-01", Date::from_timestamp(1_733_011_200).to_string());
+        assert_eq!("2024-12-15", Date::from_timestamp(1_734_220_800).to_string());
+        assert_eq!("2024-12-31", Date::from_timestamp(1_735_603_200).to_string());
+    }
+
+    #[test]
+    fn civil_days_round_trip() {
+        let known = [
+            (0, (1970, 1, 1)),
+            (-1, (1969, 12, 31)),
+            (59, (1970, 3, 1)),
+            (10_957, (2000, 1, 1)),
+            (11_016, (2000, 2, 29)),
+            (11_017, (2000, 3, 1)),
+            (-25_508, (1900, 3, 1)),
+            (19_782, (2024, 2, 29)),
+            (-719_468, (0, 3, 1)),
+        ];
+        for (days, civil) in known {
+            assert_eq!(civil, civil_from_days(days), "{}", days);
+            assert_eq!(days, days_from_civil(civil.0, civil.1, civil.2), "{:?}", civil);
+        }
+
+        for days in (-800_000..800_000).step_by(997) {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days, days_from_civil(year, month, day));
+        }
+    }
+
+    #[test]
+    fn datetimes_from_timestamps() {
+        assert_eq!("1970-01-01 00:00:00", format_datetime(0));
+        assert_eq!("2024-06-01 14:05:03", format_datetime(1_717_250_703));
+        assert_eq!("2024-12-31 23:59:59", format_datetime(1_735_689_599));
+        assert_eq!("2024-03-07T14:09:03Z", rfc3339_fixed(1_709_820_543, None).as_str());
+        assert_eq!("2024-03-07T14:09:03.007Z", rfc3339_fixed(1_709_820_543, Some(7)).as_str());
+    }
+
+    #[test]
+    fn fixed_timestamps_match_allocated_ones() {
+        for secs in [0, 59, 951_782_400, 1_709_820_543, 1_735_689_599, 253_402_300_799, u64::MAX] {
+            let allocated = format_datetime(secs);
+            assert_eq!(allocated, datetime_fixed(secs).as_str());
+            assert_eq!(format!("{}Z", allocated.replacen(' ', "T", 1)), rfc3339_fixed(secs, None).as_str());
+            for millis in [0, 7, 999] {
+                let expected = format!("{}.{:03}Z", allocated.replacen(' ', "T", 1), millis);
+                assert_eq!(expected, rfc3339_fixed(secs, Some(millis)).as_str());
+            }
+        }
+    }
+}
@@ -1,13 +1,21 @@
 use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Clone)]
 pub struct BadExtensionError {
     pub message: String,
+    /// The extension that was given, without its dot, or `None` for a
+    /// file name without one.
+    pub found: Option<String>,
 }
 
 impl fmt::Display for BadExtensionError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Extension must end with \".log\"")
+        match &self.found {
+            Some(found) => write!(f, "Extension must end with \".log\", got \".{}\"", found),
+            None => write!(f, "Extension must end with \".log\", got none"),
+        }
     }
 }
 
@@ -46,3 +54,357 @@ impl std::error::Error for FileNameError {
         None
     }
 }
+
+
+#[derive(Debug, Clone)]
+pub struct SamePathError {
+    pub message: String,
+}
+
+impl fmt::Display for SamePathError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SamePathError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+    fn description(&self) -> &str {
+        &self.message
+    }
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct ParseEntryError {
+    pub message: String,
+}
+
+impl fmt::Display for ParseEntryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ParseEntryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+    fn description(&self) -> &str {
+        &self.message
+    }
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct NotWritableError {
+    pub message: String,
+}
+
+impl fmt::Display for NotWritableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for NotWritableError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+    fn description(&self) -> &str {
+        &self.message
+    }
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct TimeoutError {
+    pub message: String,
+}
+
+impl fmt::Display for TimeoutError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TimeoutError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+    fn description(&self) -> &str {
+        &self.message
+    }
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+
+
+#[derive(Debug, Clone)]
+pub struct SchemaVersionError {
+    pub message: String,
+}
+
+impl fmt::Display for SchemaVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SchemaVersionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+    fn description(&self) -> &str {
+        &self.message
+    }
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+    fn description(&self) -> &str {
+        &self.message
+    }
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// One or more of a logger's `Sink`s failed to take an entry. The others
+/// still got it.
+#[derive(Debug, Clone)]
+pub struct SinkError {
+    pub message: String,
+}
+
+impl fmt::Display for SinkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SinkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+    fn description(&self) -> &str {
+        &self.message
+    }
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// Nothing was listening at the socket path a sink was made for.
+#[derive(Debug, Clone)]
+pub struct SocketNotFoundError {
+    pub message: String,
+}
+
+impl fmt::Display for SocketNotFoundError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for SocketNotFoundError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+    fn description(&self) -> &str {
+        &self.message
+    }
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// A field name passed to `Logger::log_fields` that can't be written
+/// unambiguously.
+#[derive(Debug, Clone)]
+pub struct FieldNameError {
+    pub message: String,
+}
+
+impl fmt::Display for FieldNameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for FieldNameError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        None
+    }
+    fn description(&self) -> &str {
+        &self.message
+    }
+    fn cause(&self) -> Option<&dyn std::error::Error> {
+        None
+    }
+}
+
+/// An I/O error with the file it happened on and what was being done, so it
+/// reads like "failed to append to /var/log/app.log: Permission denied".
+/// The original error is its `source`.
+#[derive(Debug)]
+pub struct IoContext {
+    pub path: PathBuf,
+    /// What failed, phrased to follow "failed to", e.g. `"append to"`.
+    pub op: &'static str,
+    pub error: io::Error,
+}
+
+impl IoContext {
+    /// For `map_err`: wraps an error from `op` on `path`.
+    pub(crate) fn wrap<'a>(op: &'static str, path: &'a Path) -> impl FnOnce(io::Error) -> IoContext + 'a {
+        move |error| IoContext { path: path.to_path_buf(), op, error }
+    }
+}
+
+impl fmt::Display for IoContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to {} {}: {}", self.op, self.path.display(), self.error)
+    }
+}
+
+impl std::error::Error for IoContext {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+// Conversions so the errors above can cross into code built on
+// `io::Result` with `?`. Each keeps its message as the `io::Error`'s.
+
+impl From<BadExtensionError> for io::Error {
+    fn from(error: BadExtensionError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+impl From<FileNameError> for io::Error {
+    fn from(error: FileNameError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+impl From<SamePathError> for io::Error {
+    fn from(error: SamePathError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+impl From<ParseEntryError> for io::Error {
+    fn from(error: ParseEntryError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+impl From<NotWritableError> for io::Error {
+    fn from(error: NotWritableError) -> io::Error {
+        io::Error::new(io::ErrorKind::PermissionDenied, error)
+    }
+}
+
+impl From<TimeoutError> for io::Error {
+    fn from(error: TimeoutError) -> io::Error {
+        io::Error::new(io::ErrorKind::TimedOut, error)
+    }
+}
+
+impl From<SchemaVersionError> for io::Error {
+    fn from(error: SchemaVersionError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, error)
+    }
+}
+
+impl From<ConfigError> for io::Error {
+    fn from(error: ConfigError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+impl From<SinkError> for io::Error {
+    fn from(error: SinkError) -> io::Error {
+        io::Error::other(error)
+    }
+}
+
+impl From<SocketNotFoundError> for io::Error {
+    fn from(error: SocketNotFoundError) -> io::Error {
+        io::Error::new(io::ErrorKind::NotFound, error)
+    }
+}
+
+impl From<FieldNameError> for io::Error {
+    fn from(error: FieldNameError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidInput, error)
+    }
+}
+
+/// Keeps the kind of the underlying error.
+impl From<IoContext> for io::Error {
+    fn from(error: IoContext) -> io::Error {
+        io::Error::new(error.error.kind(), error)
+    }
+}
+
+/// Turns an error returned by this crate into an `io::Error`, for callers
+/// that thread `io::Result`: `errlog(..).map_err(into_io_error)?`. The
+/// crate's own errors get the kind their `From` conversion gives them and
+/// an `io::Error` comes back as it is. Anything else, such as a clock
+/// error, becomes `io::ErrorKind::Other` with the same message.
+pub fn into_io_error(error: Box<dyn std::error::Error>) -> io::Error {
+    convert::<io::Error>(error)
+        .or_else(convert::<BadExtensionError>)
+        .or_else(convert::<FileNameError>)
+        .or_else(convert::<SamePathError>)
+        .or_else(convert::<ParseEntryError>)
+        .or_else(convert::<NotWritableError>)
+        .or_else(convert::<TimeoutError>)
+        .or_else(convert::<SchemaVersionError>)
+        .or_else(convert::<ConfigError>)
+        .or_else(convert::<SinkError>)
+        .or_else(convert::<SocketNotFoundError>)
+        .or_else(convert::<FieldNameError>)
+        .or_else(convert::<IoContext>)
+        .unwrap_or_else(|error| io::Error::other(error.to_string()))
+}
+
+/// `error` converted if it is an `E`, otherwise handed back.
+fn convert<E>(error: Box<dyn std::error::Error>) -> Result<io::Error, Box<dyn std::error::Error>>
+where
+    E: std::error::Error + Into<io::Error> + 'static,
+{
+    error.downcast::<E>().map(|error| (*error).into())
+}
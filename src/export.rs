@@ -0,0 +1,274 @@
+//! Converting a plain log into other formats.
+
+use std::error::Error;
+use std::io::Write;
+use std::path::Path;
+
+use crate::atomic::AtomicFile;
+use crate::error;
+use crate::html;
+use crate::json;
+use crate::logfmt;
+use crate::reader::{entries, LogEntry, Record};
+use crate::stats::stats;
+use crate::syslog::{self, SyslogOptions};
+
+/// Counts from `export_json`, `export_logfmt`, `export_syslog` and
+/// `export_html`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExportReport {
+    /// Entries written in the target format.
+    pub converted: u64,
+    /// Lines that didn't parse, carried over as malformed records such as
+    /// `{"malformed": "<raw line>"}` rather than dropped.
+    pub malformed: u64,
+}
+
+/// Writes every entry of the log at `src` to `dst` as JSON Lines, one
+/// object per entry. Lines that don't parse are kept as `{"malformed": ..}`
+/// records rather than dropped. Every object starts with `"v"`, see
+/// `JSON_SCHEMA_VERSION`. `dst` is replaced in one step, and may not
+/// be `src` itself.
+pub fn export_json(src: &str, dst: &str) -> Result<ExportReport, Box<dyn Error>> {
+    export_with(src, dst, json::entry_to_json, json::malformed_to_json)
+}
+
+/// Writes every entry of the log at `src` to `dst` as logfmt, one line per
+/// entry with the keys `ts`, `code` (only for coded entries) and `msg` in
+/// that order. Lines that don't parse are kept as `malformed=..`. `dst` is
+/// replaced in one step, and may not be `src` itself.
+pub fn export_logfmt(src: &str, dst: &str) -> Result<ExportReport, Box<dyn Error>> {
+    export_with(src, dst, |entry| logfmt::entry_to_logfmt(entry, &[]), logfmt::malformed_to_logfmt)
+}
+
+/// Writes every entry of the log at `src` to `dst` as an RFC 5424 syslog
+/// line with the header fields from `options`, for forwarding with e.g.
+/// rsyslog's `imfile`. Every entry gets severity 3 (error), coded entries
+/// their code as the MSGID. Lines that don't parse are kept with the MSGID
+/// `malformed`. `dst` is replaced in one step, and may not be `src` itself.
+pub fn export_syslog(src: &str, dst: &str, options: &SyslogOptions) -> Result<ExportReport, Box<dyn Error>> {
+    export_with(src, dst, |entry| syslog::entry_to_syslog(entry, options), |raw| syslog::malformed_to_syslog(raw, options))
+}
+
+/// Writes the log at `src` to `dst` as a single HTML page with inline
+/// styles: a summary of its `stats` (entries, malformed lines and the
+/// first and last time) above a table with one row per entry. Coded
+/// entries and lines that don't parse get rows of their own color, and
+/// every message is HTML-escaped. The summary takes a first pass over
+/// `src` and the rows a second, streamed one at a time. `dst` is replaced
+/// in one step, and may not be `src` itself.
+pub fn export_html(src: &str, dst: &str) -> Result<ExportReport, Box<dyn Error>> {
+    let summary = stats(src)?;
+    let title = format!("errlog report for {}", src);
+    let header = format!("{}\n", html::page_start(&title, &summary));
+    let footer = format!("{}\n", html::PAGE_END);
+    export_framed(src, dst, (&header, &footer), html::entry_to_row, html::malformed_to_row)
+}
+
+/// Writes each record of `src` to `dst` as a line made by `entry_line` or
+/// `malformed_line`.
+fn export_with(
+    src: &str,
+    dst: &str,
+    entry_line: impl Fn(&LogEntry) -> String,
+    malformed_line: impl Fn(&str) -> String,
+) -> Result<ExportReport, Box<dyn Error>> {
+    export_framed(src, dst, ("", ""), entry_line, malformed_line)
+}
+
+/// `export_with` with `frame.0` written before the first line and `frame.1`
+/// after the last.
+fn export_framed(
+    src: &str,
+    dst: &str,
+    frame: (&str, &str),
+    entry_line: impl Fn(&LogEntry) -> String,
+    malformed_line: impl Fn(&str) -> String,
+) -> Result<ExportReport, Box<dyn Error>> {
+    let records = entries(src)?;
+    if crate::is_same_file(src, dst)? {
+        return Err(Box::new(error::SamePathError {message: String::from("export destination must not be the source log")}));
+    }
+
+    let mut report = ExportReport::default();
+    let mut out = AtomicFile::create(Path::new(dst))?;
+    out.write_all(frame.0.as_bytes())?;
+    for record in records {
+        let line = match record? {
+            Record::Entry(entry) => {
+                report.converted += 1;
+                entry_line(&entry)
+            }
+            Record::Malformed { raw, .. } => {
+                report.malformed += 1;
+                malformed_line(&raw)
+            }
+        };
+        out.write_all(line.as_bytes())?;
+        out.write_all(b"\n")?;
+    }
+    out.write_all(frame.1.as_bytes())?;
+
+    out.commit()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::json::tokenizer::{decode_object, Value};
+    use crate::json::JSON_SCHEMA_VERSION;
+    use crate::test_support::TempLogDir;
+
+    #[test]
+    fn export_round_trip() {
+        let dir = TempLogDir::new();
+        let src = &dir.file("export-json-test.log");
+        let dst = &dir.file("export-json-test.jsonl");
+        fs::write(src, "\n1690999200 - plain \"quoted\"\n\n1690999212 - [E0042] - coded\nnot an entry\n").unwrap();
+
+        let report = export_json(src, dst).unwrap();
+        assert_eq!(ExportReport { converted: 2, malformed: 1 }, report);
+
+        let exported = fs::read_to_string(dst).unwrap();
+        let lines: Vec<_> = exported.lines().map(decode_object).collect();
+        assert_eq!(3, lines.len());
+        assert_eq!(
+            vec![
+                (String::from("v"), Value::Num(JSON_SCHEMA_VERSION.to_string())),
+                (String::from("timestamp"), Value::Num(String::from("1690999200"))),
+                (String::from("message"), Value::Str(String::from("plain \"quoted\""))),
+            ],
+            lines[0]
+        );
+        assert_eq!((String::from("code"), Value::Num(String::from("42"))), lines[1][2]);
+        assert_eq!(
+            vec![
+                (String::from("v"), Value::Num(JSON_SCHEMA_VERSION.to_string())),
+                (String::from("malformed"), Value::Str(String::from("not an entry"))),
+            ],
+            lines[2]
+        );
+    }
+
+    #[test]
+    fn json_reads_back_what_export_wrote() {
+        let dir = TempLogDir::new();
+        let src = &dir.file("export-json-read-test.log");
+        let dst = &dir.file("export-json-read-test.jsonl");
+        let messages = [
+            "plain",
+            "quote \" and \"\"",
+            "back\\slash \\\"",
+            "bell \u{07} escape \u{1b} tab \t nul \u{0}",
+            "emoji \u{1F600} and \u{2603}",
+            "two\nlines",
+            "crlf\r\nbreak",
+            "  padded  ",
+        ];
+        let mut log = String::new();
+        for (i, message) in messages.iter().enumerate() {
+            let entry = crate::LogEntry {
+                timestamp: 100 + i as u64,
+                millis: None,
+                code: if i % 2 == 0 { None } else { Some(i as u32) },
+                message: String::from(*message),
+                checksum: None,
+                fields: Default::default(),
+            };
+            log.push_str(&format!("\n{}\n", entry));
+        }
+        log.push_str("not an entry\n");
+        fs::write(src, log).unwrap();
+
+        export_json(src, dst).unwrap();
+        let read = crate::read_entries_json(dst).unwrap();
+        assert_eq!(messages.to_vec(), read.iter().map(|entry| entry.message.as_str()).collect::<Vec<_>>());
+        assert_eq!(crate::read_entries(src).unwrap(), read);
+
+        let records: Vec<Record> = crate::entries_json(dst).unwrap().map(Result::unwrap).collect();
+        assert_eq!(Some(&Record::Malformed { line: 9, raw: String::from("not an entry") }), records.last());
+
+        // unknown keys are kept, broken lines don't stop the rest
+        fs::write(dst, "{\"v\":1,\"timestamp\":5,\"message\":\"x\",\"host\":\"a\",\"pid\":42}\n\n{\"v\":1,\"timest\n{\"timestamp\":6,\"message\":\"y\"}\n").unwrap();
+        let records: Vec<Record> = crate::entries_json(dst).unwrap().map(Result::unwrap).collect();
+        match &records[0] {
+            Record::Entry(entry) => {
+                let fields: Vec<(&str, &str)> = entry.fields.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+                assert_eq!(vec![("host", "a"), ("pid", "42")], fields);
+                assert_eq!(entry, &crate::LogEntry::from_json(&entry.to_json()).unwrap());
+            }
+            other => panic!("Expected an entry, got {:?}.", other),
+        }
+        assert_eq!(Record::Malformed { line: 3, raw: String::from("{\"v\":1,\"timest") }, records[1]);
+        assert_eq!(3, records.len());
+
+        fs::write(dst, "{\"v\":2,\"timestamp\":5,\"message\":\"x\"}\n").unwrap();
+        if crate::read_entries_json(dst).is_ok() {
+            panic!("A future schema version should have been refused.");
+        }
+    }
+
+    #[test]
+    fn export_logfmt_lines() {
+        let dir = TempLogDir::new();
+        let src = &dir.file("export-logfmt-test.log");
+        let dst = &dir.file("export-logfmt-test.logfmt");
+        fs::write(src, "\n1709820543 - connection refused\n\n1709820544 - [E0042] - two\n\t| lines\nnot an entry\n").unwrap();
+
+        let report = export_logfmt(src, dst).unwrap();
+        assert_eq!(ExportReport { converted: 2, malformed: 1 }, report);
+        assert_eq!(
+            "ts=2024-03-07T14:09:03Z msg=\"connection refused\"\n\
+             ts=2024-03-07T14:09:04Z code=42 msg=\"two\\nlines\"\n\
+             malformed=\"not an entry\"\n",
+            fs::read_to_string(dst).unwrap()
+        );
+    }
+
+    #[test]
+    fn export_html_page() {
+        let dir = TempLogDir::new();
+        let src = &dir.file("export-html-test.log");
+        let dst = &dir.file("export-html-test.html");
+        fs::write(
+            src,
+            "\n1717250703 - <script>alert(\"x\")</script> & more\n\n1717250760 - [E0042] - disk 'full'\nnot an entry\n\n1717337103 - next day\n",
+        )
+        .unwrap();
+
+        let report = export_html(src, dst).unwrap();
+        assert_eq!(ExportReport { converted: 3, malformed: 1 }, report);
+
+        let page = fs::read_to_string(dst).unwrap();
+        assert!(page.starts_with("<!DOCTYPE html>\n"));
+        assert!(page.ends_with("</table>\n</body>\n</html>\n"));
+        assert!(!page.contains("<script>"));
+        assert!(page.contains("<td class=\"message\">&lt;script&gt;alert(&quot;x&quot;)&lt;/script&gt; &amp; more</td>"));
+        assert!(page.contains(
+            "<ul class=\"summary\">\n\
+             <li>entries: 3</li>\n\
+             <li>malformed lines: 1</li>\n\
+             <li>first: 2024-06-01 14:05:03 UTC</li>\n\
+             <li>last: 2024-06-02 14:05:03 UTC</li>\n\
+             </ul>"
+        ));
+        assert!(page.contains("<tr class=\"coded\"><td>2024-06-01 14:06:00</td><td>E0042</td><td class=\"message\">disk &#39;full&#39;</td></tr>"));
+        assert!(page.contains("<tr class=\"malformed\"><td></td><td></td><td class=\"message\">not an entry</td></tr>"));
+        assert_eq!(4, page.matches("</td></tr>").count());
+    }
+
+    #[test]
+    fn export_refuses_to_overwrite_source() {
+        let dir = TempLogDir::new();
+        let src = &dir.file("export-same-test.log");
+        fs::write(src, "1690999200 - keep me\n").unwrap();
+
+        let roundabout = dir.path().join("..").join(dir.path().file_name().unwrap()).join("export-same-test.log");
+        if export_json(src, &roundabout.to_string_lossy()).is_ok() {
+            panic!("Exporting onto the source log should have failed.");
+        }
+        assert_eq!("1690999200 - keep me\n", fs::read_to_string(src).unwrap());
+    }
+}
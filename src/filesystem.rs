@@ -0,0 +1,77 @@
+//! The file operations a `Logger` writes through, so tests can put a fake
+//! filesystem in place of the disk.
+
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+/// What `FileSystem::metadata` reports about a path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// Size in bytes, 0 for a directory.
+    pub len: u64,
+    pub is_dir: bool,
+}
+
+/// Where a `Logger` creates its directory and reads, writes and renames its
+/// file, see `Logger::new_with_file_system`. Errors are passed on as they
+/// are, so an implementation decides which `io::ErrorKind` a caller sees.
+pub trait FileSystem: fmt::Debug + Send + Sync {
+    /// Creates the single directory `path`, whose parent must already exist.
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Opens `path` for appending, creating it if it's missing but never
+    /// truncating it.
+    fn open_append<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Write + 'a>>;
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Replaces whatever is at `path` with `contents`, and only returns
+    /// once they're durable.
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()>;
+
+    /// Moves `from` over `to` in one step.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    fn remove(&self, path: &Path) -> io::Result<()>;
+}
+
+/// The disk, through `std::fs`. What `Logger::new` uses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RealFileSystem;
+
+impl FileSystem for RealFileSystem {
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir(path)
+    }
+
+    fn open_append<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Write + 'a>> {
+        Ok(Box::new(fs::OpenOptions::new().create(true).append(true).open(path)?))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let meta = fs::metadata(path)?;
+        Ok(FileMetadata { len: if meta.is_dir() { 0 } else { meta.len() }, is_dir: meta.is_dir() })
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        let mut file = fs::File::create(path)?;
+        file.write_all(contents)?;
+        file.sync_all()
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(path)
+    }
+}
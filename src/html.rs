@@ -0,0 +1,85 @@
+//! A self-contained HTML page of a log, for sharing with people who won't
+//! open the raw file.
+
+use crate::date::datetime_fixed;
+use crate::reader::LogEntry;
+use crate::stats::LogStats;
+
+/// Inline styles, so the page needs nothing besides itself.
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;color:#222}\
+table{border-collapse:collapse;width:100%}\
+th,td{border:1px solid #ccc;padding:4px 8px;text-align:left;vertical-align:top}\
+th{background:#eee}\
+td.message{font-family:monospace;white-space:pre-wrap}\
+tr.coded{background:#fdecea}\
+tr.malformed{background:#fff8e1;color:#777}";
+
+/// Appends `text` to `out` with `&`, `<`, `>`, `"` and `'` written as
+/// character references, safe both as element content and inside quoted
+/// attributes.
+pub(crate) fn push_escaped(out: &mut String, text: &str) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// Everything up to and including the table's header row: the title, the
+/// styles and a summary of `stats`.
+pub(crate) fn page_start(title: &str, stats: &LogStats) -> String {
+    let mut out = String::from("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>");
+    push_escaped(&mut out, title);
+    out.push_str(&format!("</title>\n<style>{}</style>\n</head>\n<body>\n<h1>", STYLE));
+    push_escaped(&mut out, title);
+    out.push_str("</h1>\n<ul class=\"summary\">\n");
+    out.push_str(&format!("<li>entries: {}</li>\n", stats.total));
+    out.push_str(&format!("<li>malformed lines: {}</li>\n", stats.malformed));
+    if let (Some(first), Some(last)) = (stats.first, stats.last) {
+        out.push_str(&format!("<li>first: {} UTC</li>\n", datetime_fixed(first)));
+        out.push_str(&format!("<li>last: {} UTC</li>\n", datetime_fixed(last)));
+    }
+    out.push_str("</ul>\n<table>\n<tr><th>time (UTC)</th><th>code</th><th>message</th></tr>");
+    out
+}
+
+/// Closes what `page_start` opened.
+pub(crate) const PAGE_END: &str = "</table>\n</body>\n</html>";
+
+/// A table row for `entry`, with class `coded` when it has a code.
+pub(crate) fn entry_to_row(entry: &LogEntry) -> String {
+    let (class, code) = match entry.code {
+        Some(code) => (" class=\"coded\"", format!("E{:0width$}", code, width = crate::DEFAULT_CODE_WIDTH)),
+        None => ("", String::new()),
+    };
+    let mut out = format!("<tr{}><td>{}</td><td>{}</td><td class=\"message\">", class, datetime_fixed(entry.timestamp), code);
+    push_escaped(&mut out, &entry.message);
+    out.push_str("</td></tr>");
+    out
+}
+
+/// A table row of class `malformed` holding a line that didn't parse.
+pub(crate) fn malformed_to_row(raw: &str) -> String {
+    let mut out = String::from("<tr class=\"malformed\"><td></td><td></td><td class=\"message\">");
+    push_escaped(&mut out, raw);
+    out.push_str("</td></tr>");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn markup_is_escaped() {
+        let mut out = String::new();
+        push_escaped(&mut out, "<a href=\"x\" title='y'>&amp;</a>");
+        assert_eq!("&lt;a href=&quot;x&quot; title=&#39;y&#39;&gt;&amp;amp;&lt;/a&gt;", out);
+        assert_eq!("<tr class=\"malformed\"><td></td><td></td><td class=\"message\">a &lt; b</td></tr>", malformed_to_row("a < b"));
+    }
+}
@@ -0,0 +1,229 @@
+//! Cheap overviews of log files: one file read only at its ends, or a
+//! directory's logs by size.
+
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::PathBuf;
+
+use crate::logger::is_marker;
+use crate::reader::{continuation, entries, parse_line, Record};
+
+/// Bytes read from the end of the file at first when looking for the last
+/// entry. Doubled until an entry turns up or the whole file was read.
+const TAIL_CHUNK: u64 = 4096;
+
+/// What `info` found out about a log.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogInfo {
+    /// File size in bytes.
+    pub size: u64,
+    /// Number of entries, only counted when `info` is asked to.
+    pub entry_count: Option<u64>,
+    /// Byte offset where the line of the last entry starts.
+    pub last_entry_offset: Option<u64>,
+    /// Timestamp of the first entry, in seconds since `UNIX_EPOCH`.
+    pub first_timestamp: Option<u64>,
+    /// Timestamp of the last entry, in seconds since `UNIX_EPOCH`.
+    pub last_timestamp: Option<u64>,
+    /// The last non-blank line is part of an entry and ends with a newline.
+    /// `false` for an empty file or a torn final line.
+    pub ends_complete: bool,
+}
+
+/// Summarises the log at `path` by reading up to its first entry and
+/// back from its end to the last entry. With `count` the whole file is
+/// streamed once more to fill in `LogInfo::entry_count`.
+pub fn info(path: &str, count: bool) -> Result<LogInfo, Box<dyn Error>> {
+    let mut file = fs::File::open(path)?;
+    let size = file.metadata()?.len();
+    let mut info = LogInfo { size, ..LogInfo::default() };
+
+    for record in entries(path)? {
+        if let Record::Entry(entry) = record? {
+            info.first_timestamp = Some(entry.timestamp);
+            break;
+        }
+    }
+
+    let mut chunk = TAIL_CHUNK;
+    loop {
+        let start = size.saturating_sub(chunk);
+        let mut tail = Vec::with_capacity((size - start) as usize);
+        file.seek(SeekFrom::Start(start))?;
+        file.by_ref().take(size - start).read_to_end(&mut tail)?;
+
+        if let Some((offset, timestamp)) = scan_tail(&tail, start, &mut info) {
+            info.last_entry_offset = Some(offset);
+            info.last_timestamp = Some(timestamp);
+            break;
+        }
+        if start == 0 {
+            break;
+        }
+        chunk *= 2;
+    }
+
+    if count {
+        let mut total = 0;
+        for record in entries(path)? {
+            if let Record::Entry(_) = record? {
+                total += 1;
+            }
+        }
+        info.entry_count = Some(total);
+    }
+
+    Ok(info)
+}
+
+/// Every `.log` file directly inside `dir` with its size in bytes, largest
+/// first and equal sizes by path, to see which logs are worth pruning.
+/// Subdirectories aren't searched.
+pub fn disk_usage_report(dir: &str) -> Result<Vec<(PathBuf, u64)>, Box<dyn Error>> {
+    let mut report = Vec::new();
+    for item in fs::read_dir(dir)? {
+        let item = item?;
+        let path = item.path();
+        if path.extension().is_some_and(|ext| ext == "log") && item.file_type()?.is_file() {
+            report.push((path, item.metadata()?.len()));
+        }
+    }
+
+    report.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    Ok(report)
+}
+
+/// Looks for the last entry line in `tail`, which starts at byte `start`
+/// of the file, and sets `ends_complete` along the way. A first line
+/// that may be cut off is ignored unless `tail` starts the file.
+fn scan_tail(tail: &[u8], start: u64, info: &mut LogInfo) -> Option<(u64, u64)> {
+    let mut lines = Vec::new();
+    let mut offset = start;
+    for line in tail.split(|&b| b == b'\n') {
+        lines.push((offset, line));
+        offset += line.len() as u64 + 1;
+    }
+    let complete = lines.last().is_some_and(|(_, line)| line.is_empty());
+    if complete {
+        lines.pop();
+    }
+    if start > 0 {
+        lines.remove(0);
+    }
+
+    let mut last_line = true;
+    for (offset, line) in lines.into_iter().rev() {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim_end_matches('\r');
+        if text.is_empty() {
+            continue;
+        }
+
+        let entry = parse_line(text);
+        if last_line {
+            info.ends_complete = complete && (entry.is_some() || continuation(text).is_some() || is_marker(text));
+            last_line = false;
+        }
+        if let Some(entry) = entry {
+            return Some((offset, entry.timestamp));
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempLogDir;
+
+    #[test]
+    fn info_for_empty_and_single_entry_files() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("info-small-test.log");
+        fs::write(path, "").unwrap();
+        assert_eq!(LogInfo { entry_count: Some(0), ..LogInfo::default() }, info(path, true).unwrap());
+
+        fs::write(path, "\n100 - only\n").unwrap();
+        assert_eq!(
+            LogInfo {
+                size: 12,
+                entry_count: Some(1),
+                last_entry_offset: Some(1),
+                first_timestamp: Some(100),
+                last_timestamp: Some(100),
+                ends_complete: true,
+            },
+            info(path, true).unwrap()
+        );
+        assert_eq!(None, info(path, false).unwrap().entry_count);
+    }
+
+    #[test]
+    fn disk_usage_largest_first() {
+        let temp = TempLogDir::new();
+        let dir = &temp.file("disk-usage-test");
+        fs::create_dir_all(format!("{}/nested.log", dir)).unwrap();
+        fs::write(format!("{}/small.log", dir), "1").unwrap();
+        fs::write(format!("{}/big.log", dir), "1234567890").unwrap();
+        fs::write(format!("{}/also-small.log", dir), "2").unwrap();
+        fs::write(format!("{}/medium.log", dir), "12345").unwrap();
+        fs::write(format!("{}/notes.txt", dir), "not a log at all").unwrap();
+
+        let report: Vec<(String, u64)> = disk_usage_report(dir)
+            .unwrap()
+            .into_iter()
+            .map(|(path, size)| (path.file_name().unwrap().to_string_lossy().into_owned(), size))
+            .collect();
+        assert_eq!(
+            vec![
+                (String::from("big.log"), 10),
+                (String::from("medium.log"), 5),
+                (String::from("also-small.log"), 1),
+                (String::from("small.log"), 1),
+            ],
+            report
+        );
+        assert!(disk_usage_report(&temp.file("does-not-exist")).is_err());
+    }
+
+    #[test]
+    fn info_for_torn_and_multi_entry_files() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("info-large-test.log");
+        fs::write(path, "\n100 - a\n\n200 - b\n\t| more b\ngarbage\n\n300 - c").unwrap();
+        assert_eq!(
+            LogInfo {
+                size: 44,
+                entry_count: Some(3),
+                last_entry_offset: Some(37),
+                first_timestamp: Some(100),
+                last_timestamp: Some(300),
+                ends_complete: false,
+            },
+            info(path, true).unwrap()
+        );
+
+        // enough junk after the last entry that it's past the first tail chunk
+        let mut log = String::from("junk\n");
+        for i in 0..1000 {
+            log.push_str(&format!("\n{} - entry number {}\n", 1_000 + i, i));
+        }
+        log.push_str("\n===== run started 1970-01-01 00:00:00 =====\n");
+        log.push_str(&"not an entry\n".repeat(1000));
+        fs::write(path, &log).unwrap();
+        let found = info(path, true).unwrap();
+        assert_eq!(log.len() as u64, found.size);
+        assert_eq!(Some(1_000), found.entry_count);
+        assert_eq!(Some(1_000), found.first_timestamp);
+        assert_eq!(Some(1_999), found.last_timestamp);
+        assert_eq!(log.find("\n1999 - ").map(|at| at as u64 + 1), found.last_entry_offset);
+        assert!(!found.ends_complete);
+
+        fs::write(path, "\n100 - a\n\n200 - b\n\t| more b\n").unwrap();
+        let found = info(path, false).unwrap();
+        assert_eq!(Some(10), found.last_entry_offset);
+        assert!(found.ends_complete);
+    }
+}
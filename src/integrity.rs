@@ -0,0 +1,542 @@
+//! Checks for logs that may have been tampered with or damaged.
+
+use std::error::Error;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::logger::is_marker;
+use crate::reader::{continuation, entries, parse_line, split_checksum, Record};
+
+/// Returns the indices (among entries, starting at 0) of every entry whose
+/// timestamp is earlier than the entry before it. A non-empty result hints
+/// at an edited file or a clock that jumped backwards.
+pub fn check_monotonic(path: &str) -> Result<Vec<usize>, Box<dyn Error>> {
+    let mut out_of_order = Vec::new();
+    let mut previous = None;
+    let mut index = 0;
+
+    for record in entries(path)? {
+        if let Record::Entry(entry) = record? {
+            if previous.is_some_and(|previous| entry.timestamp < previous) {
+                out_of_order.push(index);
+            }
+            previous = Some(entry.timestamp);
+            index += 1;
+        }
+    }
+
+    Ok(out_of_order)
+}
+
+/// How `Logger::with_checksums` protects each line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksums {
+    /// Each checksum covers only its own line, see `verify`.
+    PerLine,
+    /// Each checksum also covers the previous line's checksum, so removed
+    /// or reordered lines are caught too, see `verify_chained`.
+    Chained,
+}
+
+/// 32-bit FNV-1a over `previous` (big endian, if any) followed by `content`.
+pub(crate) fn checksum(previous: Option<u32>, content: &[u8]) -> u32 {
+    let mut hash: u32 = 0x811c_9dc5;
+    let previous = previous.map(u32::to_be_bytes);
+    for &byte in previous.iter().flatten().chain(content) {
+        hash ^= u32::from(byte);
+        hash = hash.wrapping_mul(0x0100_0193);
+    }
+    hash
+}
+
+/// The checksum of the last checksummed line of the log at `path`, which
+/// a chained logger continues from.
+pub(crate) fn last_checksum(path: &str) -> Result<Option<u32>, Box<dyn Error>> {
+    let mut last = None;
+    for record in entries(path)?.with_checksums() {
+        if let Record::Entry(entry) = record? {
+            last = entry.checksum.or(last);
+        }
+    }
+    Ok(last)
+}
+
+/// Outcome of `verify` or `verify_chained`. Line numbers start at 1, and an
+/// entry with continuation lines is reported by its first line.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VerifyReport {
+    /// Lines that carried a checksum.
+    pub checked: u64,
+    /// Lines whose checksum doesn't match their content.
+    pub failed: Vec<usize>,
+    /// Non-blank lines without a checksum, such as entries written before
+    /// checksums were turned on or inserted by hand.
+    pub missing: Vec<usize>,
+}
+
+impl VerifyReport {
+    pub fn is_clean(&self) -> bool {
+        self.failed.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Recomputes the checksum of every line in a log written with
+/// `Checksums::PerLine` and reports the lines that don't match.
+pub fn verify(path: &str) -> Result<VerifyReport, Box<dyn Error>> {
+    verify_lines(path, false)
+}
+
+/// Same as `verify` for `Checksums::Chained`. Each line is checked against
+/// the checksum stored on the line before it, so a single altered line is
+/// flagged on its own, while a removed line flags the one after it.
+pub fn verify_chained(path: &str) -> Result<VerifyReport, Box<dyn Error>> {
+    verify_lines(path, true)
+}
+
+fn verify_lines(path: &str, chained: bool) -> Result<VerifyReport, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let (lines, _) = split_lines(&bytes);
+
+    // entries with their continuation lines, numbered by their first line
+    let mut records: Vec<(usize, String)> = Vec::new();
+    let mut continues = false;
+    for (index, line) in lines.iter().enumerate() {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim_end_matches('\r');
+        if text.is_empty() || is_marker(text) {
+            continues = false;
+            continue;
+        }
+
+        match records.last_mut() {
+            Some((_, record)) if continues && continuation(text).is_some() => {
+                record.push('\n');
+                record.push_str(text);
+            }
+            _ => records.push((index + 1, text.to_owned())),
+        }
+        continues = true;
+    }
+
+    let mut report = VerifyReport::default();
+    let mut previous = None;
+    for (number, record) in records {
+        match split_checksum(&record) {
+            (content, Some(stored)) => {
+                report.checked += 1;
+                let seed = if chained { previous } else { None };
+                if checksum(seed, content.as_bytes()) != stored {
+                    report.failed.push(number);
+                }
+                previous = Some(stored);
+            }
+            (_, None) => report.missing.push(number),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Something `validate` found wrong with a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Problem {
+    /// The last line has no trailing newline, usually a write cut short.
+    TornLine,
+    /// The line has text but no parseable timestamp.
+    Unparseable,
+    /// The line contains NUL bytes.
+    InteriorNul,
+    /// The line is empty and doesn't start a record: it's followed by
+    /// another blank line, a continuation line or the end of the file.
+    BlankLine,
+}
+
+/// Problems found by `validate`, each with its 1-based line number, in
+/// file order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationReport {
+    pub problems: Vec<(usize, Problem)>,
+}
+
+impl ValidationReport {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// What `repair` did with a torn final line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TornLineFix {
+    /// The line was a complete entry, so only the newline was added.
+    Completed,
+    /// The line wasn't a usable entry and was moved to this sidecar file.
+    Quarantined(PathBuf),
+}
+
+/// Changes made by `repair`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RepairReport {
+    pub nuls_stripped: usize,
+    pub blank_lines_removed: usize,
+    pub torn_line: Option<TornLineFix>,
+}
+
+impl RepairReport {
+    pub fn changed(&self) -> bool {
+        self.nuls_stripped > 0 || self.blank_lines_removed > 0 || self.torn_line.is_some()
+    }
+}
+
+/// Splits `bytes` into lines, returning whether the last one is missing its
+/// newline.
+fn split_lines(bytes: &[u8]) -> (Vec<&[u8]>, bool) {
+    let mut lines: Vec<&[u8]> = bytes.split(|&b| b == b'\n').collect();
+    let torn = !lines.last().is_some_and(|last| last.is_empty());
+    if !torn {
+        lines.pop();
+    }
+
+    (lines, torn)
+}
+
+fn strip_nuls(line: &[u8]) -> Vec<u8> {
+    line.iter().copied().filter(|&b| b != 0).collect()
+}
+
+/// An entry, or a line the crate writes on purpose such as a run separator,
+/// a heartbeat or a continuation line.
+fn is_entry(line: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(line);
+    let text = text.trim_end_matches('\r');
+    parse_line(text).is_some() || is_marker(text) || continuation(text).is_some()
+}
+
+fn is_blank(line: &[u8]) -> bool {
+    line.is_empty() || line == b"\r"
+}
+
+/// Whether the blank line at `index` is more than the `LF` a record starts
+/// with, which is what the line after it has to be.
+fn is_stray_blank<L: AsRef<[u8]>>(lines: &[L], index: usize) -> bool {
+    match lines.get(index + 1).map(|next| strip_nuls(next.as_ref())) {
+        Some(next) if !is_blank(&next) => continuation(String::from_utf8_lossy(&next).trim_end_matches('\r')).is_some(),
+        _ => true,
+    }
+}
+
+/// Inspects the log at `path` for damage without changing it: a torn final
+/// line, lines without a parseable timestamp, NUL bytes and blank lines
+/// other than the one every record starts with. A log as `Logger` writes
+/// it is clean.
+pub fn validate(path: &str) -> Result<ValidationReport, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let (lines, torn) = split_lines(&bytes);
+    let mut report = ValidationReport::default();
+
+    for (index, line) in lines.iter().enumerate() {
+        let number = index + 1;
+        if line.contains(&0) {
+            report.problems.push((number, Problem::InteriorNul));
+        }
+
+        let line = strip_nuls(line);
+        if is_blank(&line) {
+            if is_stray_blank(&lines, index) {
+                report.problems.push((number, Problem::BlankLine));
+            }
+        } else if !is_entry(&line) {
+            report.problems.push((number, Problem::Unparseable));
+        }
+
+        if torn && number == lines.len() {
+            report.problems.push((number, Problem::TornLine));
+        }
+    }
+
+    Ok(report)
+}
+
+/// Rewrites the log at `path` with NUL bytes stripped and the blank lines
+/// `validate` reports removed. A torn final line is given its newline if
+/// it's a complete entry, otherwise it's appended to `<path>.quarantine`.
+/// Lines that are merely unparseable are kept. Running this on an already
+/// repaired file, or on a healthy one, changes nothing.
+pub fn repair(path: &str) -> Result<RepairReport, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let (lines, torn) = split_lines(&bytes);
+    let mut report = RepairReport::default();
+
+    let mut kept = Vec::with_capacity(lines.len());
+    for (index, line) in lines.iter().enumerate() {
+        let cleaned = strip_nuls(line);
+        report.nuls_stripped += line.len() - cleaned.len();
+
+        if torn && index + 1 == lines.len() {
+            if is_blank(&cleaned) {
+                continue;
+            }
+            if is_entry(&cleaned) {
+                report.torn_line = Some(TornLineFix::Completed);
+            } else {
+                report.torn_line = Some(TornLineFix::Quarantined(quarantine(path, &cleaned)?));
+                continue;
+            }
+        }
+        kept.push(cleaned);
+    }
+
+    // decided on what's kept, so a blank before a quarantined line goes too
+    let mut repaired = Vec::with_capacity(bytes.len());
+    for (index, line) in kept.iter().enumerate() {
+        if is_blank(line) && is_stray_blank(&kept, index) {
+            report.blank_lines_removed += 1;
+            continue;
+        }
+        repaired.extend_from_slice(line);
+        repaired.push(b'\n');
+    }
+
+    if repaired != bytes {
+        crate::atomic::write_atomically(Path::new(path), &repaired)?;
+    }
+    Ok(report)
+}
+
+/// Rewrites the log at `path` without blank lines and without records that
+/// repeat the record right before them byte for byte, as retried writes
+/// leave behind. A record is a line with the continuation lines after it,
+/// so a message that repeats one of its own lines stays whole. Returns
+/// the number of lines removed; the file is only replaced, in one step,
+/// when there's something to remove. Records come out without the leading
+/// `LF` a `Logger` writes, which `crate::FORMAT_VERSION` allows for.
+///
+/// Entries appended while this runs would be lost with the old file, so
+/// don't compact a log something is still writing to.
+pub fn compact_log(path: &str) -> Result<usize, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+    let (lines, torn) = split_lines(&bytes);
+
+    let mut records: Vec<Vec<&[u8]>> = Vec::new();
+    let mut removed = 0;
+    for line in &lines {
+        if is_blank(line) {
+            removed += 1;
+            continue;
+        }
+        let text = String::from_utf8_lossy(line);
+        match records.last_mut() {
+            Some(record) if continuation(text.trim_end_matches('\r')).is_some() => record.push(line),
+            _ => records.push(vec![line]),
+        }
+    }
+    if removed == 0 && records.windows(2).all(|pair| pair[0] != pair[1]) {
+        return Ok(0);
+    }
+
+    let mut compacted = Vec::with_capacity(bytes.len());
+    let mut previous: Option<&Vec<&[u8]>> = None;
+    for record in &records {
+        if previous == Some(record) {
+            removed += record.len();
+            continue;
+        }
+        for line in record {
+            compacted.extend_from_slice(line);
+            compacted.push(b'\n');
+        }
+        previous = Some(record);
+    }
+    // leave a torn final line as torn, repairing it is `repair`'s job
+    if torn && previous.and_then(|record| record.last()) == lines.last() {
+        compacted.pop();
+    }
+
+    crate::atomic::write_atomically(Path::new(path), &compacted)?;
+    Ok(removed)
+}
+
+fn quarantine(path: &str, line: &[u8]) -> Result<PathBuf, Box<dyn Error>> {
+    let quarantine_path = PathBuf::from(format!("{}.quarantine", path));
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&quarantine_path)?;
+    file.write_all(line)?;
+    file.write_all(b"\n")?;
+
+    Ok(quarantine_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempLogDir;
+
+    #[test]
+    fn out_of_order_entry_is_reported() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("monotonic-test.log");
+        fs::write(path, "\n100 - a\n\n200 - b\ngarbage\n150 - c\n\n150 - d\n300 - e\n").unwrap();
+        assert_eq!(vec![2], check_monotonic(path).unwrap());
+
+        fs::write(path, "100 - a\n100 - b\n101 - c\n").unwrap();
+        assert!(check_monotonic(path).unwrap().is_empty());
+    }
+
+    #[test]
+    fn fnv1a_known_values() {
+        assert_eq!(0x811c_9dc5, checksum(None, b""));
+        assert_eq!(0xe40c_292c, checksum(None, b"a"));
+        assert_eq!(0xbf9c_f968, checksum(None, b"foobar"));
+    }
+
+    #[test]
+    fn verify_flags_the_flipped_line() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("verify-checksum-test.log");
+        let logger = crate::Logger::new(path).unwrap().with_checksums(Checksums::PerLine);
+        for message in ["first entry", "second entry", "third entry\nwith more"] {
+            logger.log(String::from(message)).unwrap();
+        }
+
+        let report = verify(path).unwrap();
+        assert_eq!(3, report.checked);
+        assert!(report.is_clean());
+        let found: Vec<crate::LogEntry> = entries(path)
+            .unwrap()
+            .with_checksums()
+            .filter_map(|record| match record.unwrap() {
+                Record::Entry(entry) => Some(entry),
+                Record::Malformed { .. } => None,
+            })
+            .collect();
+        assert_eq!("second entry", found[1].message);
+        assert!(found.iter().all(|entry| entry.checksum.is_some()));
+
+        let mut bytes = fs::read(path).unwrap();
+        let at = String::from_utf8_lossy(&bytes).find("second").unwrap();
+        bytes[at] = b'S';
+        fs::write(path, &bytes).unwrap();
+
+        let report = verify(path).unwrap();
+        assert_eq!(vec![4], report.failed);
+        assert!(report.missing.is_empty());
+    }
+
+    #[test]
+    fn chained_checksums_catch_deletions() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("verify-chained-test.log");
+        let logger = crate::Logger::new(path).unwrap().with_checksums(Checksums::Chained);
+        logger.log(String::from("one")).unwrap();
+        logger.log(String::from("two")).unwrap();
+        drop(logger);
+
+        // a new logger carries the chain on from the file
+        let logger = crate::Logger::new(path).unwrap().with_checksums(Checksums::Chained);
+        logger.log(String::from("three")).unwrap();
+        logger.log(String::from("four")).unwrap();
+        assert!(verify_chained(path).unwrap().is_clean());
+        assert_eq!(vec![4, 6, 8], verify(path).unwrap().failed);
+
+        let contents = fs::read_to_string(path).unwrap();
+        let without_two: String = contents
+            .lines()
+            .filter(|line| !line.contains(" - two #"))
+            .map(|line| format!("{}\n", line))
+            .collect();
+        fs::write(path, without_two).unwrap();
+        assert_eq!(vec![5], verify_chained(path).unwrap().failed);
+
+        fs::write(path, format!("{}\n1 - inserted\n", contents)).unwrap();
+        assert_eq!(vec![10], verify_chained(path).unwrap().missing);
+    }
+
+    #[test]
+    fn validate_and_repair_torn_entry() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("repair-torn-test.log");
+        fs::write(path, b"\n100 - a\n\n\n200 - b\0\0\n\n\t| more b\ngarbage\n\0\0\0\n===== run started 1970-01-01 00:05:00 =====\n300 - c").unwrap();
+
+        let report = validate(path).unwrap();
+        assert_eq!(
+            vec![
+                (3, Problem::BlankLine),
+                (5, Problem::InteriorNul),
+                (6, Problem::BlankLine),
+                (8, Problem::Unparseable),
+                (9, Problem::InteriorNul),
+                (11, Problem::TornLine),
+            ],
+            report.problems
+        );
+
+        let report = repair(path).unwrap();
+        assert_eq!(
+            RepairReport {
+                nuls_stripped: 5,
+                blank_lines_removed: 2,
+                torn_line: Some(TornLineFix::Completed),
+            },
+            report
+        );
+        let repaired = b"\n100 - a\n\n200 - b\n\t| more b\ngarbage\n\n===== run started 1970-01-01 00:05:00 =====\n300 - c\n".to_vec();
+        assert_eq!(repaired, fs::read(path).unwrap());
+        assert_eq!(vec![(6, Problem::Unparseable)], validate(path).unwrap().problems);
+
+        // a second repair is a no-op
+        assert!(!repair(path).unwrap().changed());
+        assert_eq!(repaired, fs::read(path).unwrap());
+    }
+
+    #[test]
+    fn logs_as_written_validate_clean() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("validate-logger-test.log");
+        crate::errlog(path, String::from("from errlog")).unwrap();
+        let logger = crate::Logger::new(path).unwrap().with_run_separator(true);
+        logger.log(String::from("two\nlines")).unwrap();
+        logger.log(String::from("plain")).unwrap();
+        drop(logger);
+
+        assert!(validate(path).unwrap().is_clean(), "{:?}", validate(path).unwrap());
+        let before = fs::read(path).unwrap();
+        assert!(!repair(path).unwrap().changed());
+        assert_eq!(before, fs::read(path).unwrap());
+    }
+
+    #[test]
+    fn compact_removes_blanks_and_repeats() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("compact-test.log");
+        let seeded = "\n100 - a\n\n100 - a\n\n100 - a\n\n200 - b\n\t| same\n\t| same\n\n200 - b\n\t| same\n\t| same\n\n100 - a\n\r\n300 - c";
+        fs::write(path, seeded).unwrap();
+
+        // 7 blank lines, two repeats of `a`, one repeat of the three line `b`
+        assert_eq!(12, compact_log(path).unwrap());
+        assert_eq!("100 - a\n200 - b\n\t| same\n\t| same\n100 - a\n300 - c", fs::read_to_string(path).unwrap());
+        let messages: Vec<String> = crate::read_entries(path).unwrap().into_iter().map(|entry| entry.message).collect();
+        assert_eq!(vec!["a", "b\nsame\nsame", "a", "c"], messages);
+
+        assert_eq!(0, compact_log(path).unwrap());
+    }
+
+    #[test]
+    fn repair_quarantines_partial_entry() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("repair-quarantine-test.log");
+        let quarantine_path = &dir.file("repair-quarantine-test.log.quarantine");
+        fs::write(path, b"\n100 - a\n\n2001").unwrap();
+
+        assert_eq!(vec![(4, Problem::Unparseable), (4, Problem::TornLine)], validate(path).unwrap().problems);
+
+        let report = repair(path).unwrap();
+        assert_eq!(Some(TornLineFix::Quarantined(PathBuf::from(quarantine_path))), report.torn_line);
+        assert_eq!(1, report.blank_lines_removed);
+        assert_eq!(b"\n100 - a\n".to_vec(), fs::read(path).unwrap());
+        assert_eq!(b"2001\n".to_vec(), fs::read(quarantine_path).unwrap());
+        assert!(validate(path).unwrap().is_clean());
+        assert!(!repair(path).unwrap().changed());
+    }
+}
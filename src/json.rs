@@ -0,0 +1,409 @@
+//! Minimal JSON output, enough for the flat objects this crate emits, and
+//! a decoder for reading entries back.
+
+use std::error::Error;
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::error::{ParseEntryError, SchemaVersionError};
+use crate::reader::{LogEntry, Record};
+
+/// Value of the `"v"` field leading every JSON object this crate writes.
+/// It only goes up when a key is renamed or removed or changes meaning;
+/// readers keep accepting every older version.
+///
+/// Version 1, one object per line:
+/// - entries: `"v"`, `"timestamp"` (seconds since `UNIX_EPOCH`), `"code"`
+///   (only for coded entries) and `"message"`, in that order, then any
+///   `LogEntry::fields` as top-level keys with string values
+/// - lines that didn't parse: `"v"` and `"malformed"` (the raw line)
+///
+/// Version 0 is the same without `"v"`, as written before it existed.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+/// Appends `text` to `out` as a quoted JSON string. Quotes, backslashes and
+/// control characters are escaped; everything else is written as UTF-8.
+pub(crate) fn push_string(out: &mut String, text: &str) {
+    out.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0C}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// `{"v":1,"timestamp":<secs>,"code":<code>,"message":"<message>"}`, with
+/// `code` left out for entries that don't have one, followed by any
+/// `LogEntry::fields` as strings.
+pub(crate) fn entry_to_json(entry: &LogEntry) -> String {
+    let mut out = format!("{{\"v\":{},\"timestamp\":{}", JSON_SCHEMA_VERSION, entry.timestamp);
+    if let Some(code) = entry.code {
+        out.push_str(&format!(",\"code\":{}", code));
+    }
+    out.push_str(",\"message\":");
+    push_string(&mut out, &entry.message);
+    for (key, value) in &entry.fields {
+        out.push(',');
+        push_string(&mut out, key);
+        out.push(':');
+        push_string(&mut out, value);
+    }
+    out.push('}');
+    out
+}
+
+/// `{"v":1,"malformed":"<raw line>"}` for a line that didn't parse as an
+/// entry.
+pub(crate) fn malformed_to_json(raw: &str) -> String {
+    let mut out = format!("{{\"v\":{},\"malformed\":", JSON_SCHEMA_VERSION);
+    push_string(&mut out, raw);
+    out.push('}');
+    out
+}
+
+/// Keys of an entry object that map to `LogEntry`'s own fields. Any others
+/// are kept in `LogEntry::fields`.
+pub(crate) const ENTRY_KEYS: [&str; 4] = ["v", "timestamp", "code", "message"];
+
+/// Reads back an entry object as `LogEntry::to_json` writes it. Keys this
+/// version doesn't know end up in `LogEntry::fields`. Fails with
+/// `SchemaVersionError` for a `"v"` newer than `JSON_SCHEMA_VERSION`, and
+/// with `ParseEntryError` for anything that isn't an entry object,
+/// including malformed records.
+pub(crate) fn entry_from_json(text: &str) -> Result<LogEntry, Box<dyn Error>> {
+    match record_from_json(text, 0)? {
+        Record::Entry(entry) => Ok(entry),
+        Record::Malformed { .. } => Err(Box::new(ParseEntryError { message: format!("not a JSON log entry: {:?}", text) })),
+    }
+}
+
+/// One line of a JSON Lines log as the `Record` it was exported from: an
+/// entry object, or `Record::Malformed` with line number `line` for a
+/// `"malformed"` object (its raw line) or anything that isn't an entry
+/// object (the line itself).
+pub(crate) fn record_from_json(text: &str, line: usize) -> Result<Record, SchemaVersionError> {
+    let malformed = || Record::Malformed { line, raw: text.to_owned() };
+    let Some(pairs) = decode_flat(text) else {
+        return Ok(malformed());
+    };
+    let value = |key: &str| pairs.iter().find(|(k, _)| k == key).map(|(_, value)| value);
+
+    let Some(version) = value("v").map_or(Some(0), Flat::as_number) else {
+        return Ok(malformed());
+    };
+    if version > u64::from(JSON_SCHEMA_VERSION) {
+        return Err(SchemaVersionError {
+            message: format!("JSON schema version {} is newer than the supported {}", version, JSON_SCHEMA_VERSION),
+        });
+    }
+    if let Some(raw) = value("malformed").and_then(Flat::as_string) {
+        return Ok(Record::Malformed { line, raw: raw.to_owned() });
+    }
+
+    let Some(timestamp) = value("timestamp").and_then(Flat::as_number) else {
+        return Ok(malformed());
+    };
+    let code = match value("code") {
+        Some(code) => match code.as_number().and_then(|code| u32::try_from(code).ok()) {
+            Some(code) => Some(code),
+            None => return Ok(malformed()),
+        },
+        None => None,
+    };
+    let Some(message) = value("message").and_then(Flat::as_string) else {
+        return Ok(malformed());
+    };
+
+    let fields = pairs
+        .iter()
+        .filter(|(key, _)| !ENTRY_KEYS.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.to_string()))
+        .collect();
+    Ok(Record::Entry(LogEntry { timestamp, millis: None, code, message: message.to_owned(), checksum: None, fields }))
+}
+
+/// A value in one of the flat objects this crate writes.
+enum Flat {
+    Str(String),
+    Num(u64),
+}
+
+/// Strings as they are, numbers as their digits.
+impl fmt::Display for Flat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Flat::Str(s) => f.write_str(s),
+            Flat::Num(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl Flat {
+    fn as_number(&self) -> Option<u64> {
+        match self {
+            Flat::Num(n) => Some(*n),
+            Flat::Str(_) => None,
+        }
+    }
+
+    fn as_string(&self) -> Option<&str> {
+        match self {
+            Flat::Str(s) => Some(s),
+            Flat::Num(_) => None,
+        }
+    }
+}
+
+/// The key/value pairs of an object whose values are all strings or
+/// unsigned integers, `None` for anything else.
+fn decode_flat(text: &str) -> Option<Vec<(String, Flat)>> {
+    let mut chars = text.trim().chars().peekable();
+    let mut pairs = Vec::new();
+    if chars.next()? != '{' {
+        return None;
+    }
+
+    skip_space(&mut chars);
+    if chars.peek() == Some(&'}') {
+        chars.next();
+    } else {
+        loop {
+            skip_space(&mut chars);
+            if chars.next()? != '"' {
+                return None;
+            }
+            let key = decode_flat_string(&mut chars)?;
+            skip_space(&mut chars);
+            if chars.next()? != ':' {
+                return None;
+            }
+            skip_space(&mut chars);
+            let value = if chars.peek() == Some(&'"') {
+                chars.next();
+                Flat::Str(decode_flat_string(&mut chars)?)
+            } else {
+                let mut digits = String::new();
+                while let Some(c) = chars.peek().copied().filter(char::is_ascii_digit) {
+                    digits.push(c);
+                    chars.next();
+                }
+                Flat::Num(digits.parse().ok()?)
+            };
+            pairs.push((key, value));
+
+            skip_space(&mut chars);
+            match chars.next()? {
+                ',' => continue,
+                '}' => break,
+                _ => return None,
+            }
+        }
+    }
+
+    match chars.next() {
+        None => Some(pairs),
+        Some(_) => None,
+    }
+}
+
+fn skip_space(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|c| c.is_ascii_whitespace()) {
+        chars.next();
+    }
+}
+
+/// The rest of a string whose opening quote was just read.
+fn decode_flat_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    let mut out = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(out),
+            '\\' => match chars.next()? {
+                'n' => out.push('\n'),
+                'r' => out.push('\r'),
+                't' => out.push('\t'),
+                'b' => out.push('\u{08}'),
+                'f' => out.push('\u{0C}'),
+                'u' => {
+                    let high = decode_hex4(chars)?;
+                    let code = if (0xD800..0xDC00).contains(&high) {
+                        if chars.next()? != '\\' || chars.next()? != 'u' {
+                            return None;
+                        }
+                        let low = decode_hex4(chars)?;
+                        0x10000 + ((high - 0xD800) << 10) + low.checked_sub(0xDC00).filter(|low| *low < 0x400)?
+                    } else {
+                        high
+                    };
+                    out.push(char::from_u32(code)?);
+                }
+                c @ ('"' | '\\' | '/') => out.push(c),
+                _ => return None,
+            },
+            c if (c as u32) < 0x20 => return None,
+            c => out.push(c),
+        }
+    }
+}
+
+fn decode_hex4(chars: &mut Peekable<Chars>) -> Option<u32> {
+    let hex: String = chars.by_ref().take(4).collect();
+    // from_str_radix alone would take a sign, as in `\u+041`
+    if hex.len() != 4 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    u32::from_str_radix(&hex, 16).ok()
+}
+
+/// A deliberately separate decoder so tests don't grade the encoder with
+/// its own logic.
+#[cfg(test)]
+pub(crate) mod tokenizer {
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub(crate) enum Value {
+        Str(String),
+        Num(String),
+    }
+
+    /// Decodes one flat JSON object into its key/value pairs, in order.
+    pub(crate) fn decode_object(text: &str) -> Vec<(String, Value)> {
+        let mut chars = text.trim().chars().peekable();
+        let mut pairs = Vec::new();
+        assert_eq!(Some('{'), chars.next());
+
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(',') => continue,
+                Some('"') => {
+                    let key = decode_string(&mut chars);
+                    assert_eq!(Some(':'), chars.next());
+                    let value = match chars.peek() {
+                        Some('"') => {
+                            chars.next();
+                            Value::Str(decode_string(&mut chars))
+                        }
+                        _ => {
+                            let mut num = String::new();
+                            while let Some(&c) = chars.peek() {
+                                if c == ',' || c == '}' {
+                                    break;
+                                }
+                                num.push(c);
+                                chars.next();
+                            }
+                            Value::Num(num)
+                        }
+                    };
+                    pairs.push((key, value));
+                }
+                other => panic!("unexpected {:?} in {}", other, text),
+            }
+        }
+
+        assert_eq!(None, chars.next());
+        pairs
+    }
+
+    fn decode_string(chars: &mut impl Iterator<Item = char>) -> String {
+        let mut out = String::new();
+        loop {
+            match chars.next().expect("unterminated string") {
+                '"' => return out,
+                '\\' => match chars.next().expect("dangling escape") {
+                    'n' => out.push('\n'),
+                    'r' => out.push('\r'),
+                    't' => out.push('\t'),
+                    'b' => out.push('\u{08}'),
+                    'f' => out.push('\u{0C}'),
+                    'u' => {
+                        let hex: String = chars.take(4).collect();
+                        out.push(char::from_u32(u32::from_str_radix(&hex, 16).unwrap()).unwrap());
+                    }
+                    c => out.push(c),
+                },
+                c => {
+                    assert!(c as u32 >= 0x20, "raw control character in string");
+                    out.push(c);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tokenizer::{decode_object, Value};
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn escapes_survive_decoding() {
+        let message = "quote \" backslash \\ newline \n tab \t bell \u{07} snowman \u{2603}";
+        let entry = LogEntry { timestamp: 5, millis: None, code: Some(42), message: String::from(message), checksum: None, fields: BTreeMap::new() };
+
+        assert_eq!(
+            vec![
+                (String::from("v"), Value::Num(String::from("1"))),
+                (String::from("timestamp"), Value::Num(String::from("5"))),
+                (String::from("code"), Value::Num(String::from("42"))),
+                (String::from("message"), Value::Str(String::from(message))),
+            ],
+            decode_object(&entry_to_json(&entry))
+        );
+    }
+
+    #[test]
+    fn version_1_keys_are_pinned() {
+        let keys = |json: String| -> Vec<String> { decode_object(&json).into_iter().map(|(key, _)| key).collect() };
+        let coded = LogEntry { timestamp: 5, millis: Some(3), code: Some(42), message: String::from("x"), checksum: Some(7), fields: BTreeMap::new() };
+        let plain = LogEntry { code: None, ..coded.clone() };
+
+        assert_eq!(1, JSON_SCHEMA_VERSION);
+        assert_eq!(vec!["v", "timestamp", "code", "message"], keys(coded.to_json()));
+        assert_eq!(vec!["v", "timestamp", "message"], keys(plain.to_json()));
+        assert_eq!(vec!["v", "malformed"], keys(malformed_to_json("junk")));
+    }
+
+    #[test]
+    fn entries_read_back_from_json() {
+        let message = "quote \" backslash \\ newline \n bell \u{07} snowman \u{2603}";
+        let entry = LogEntry { timestamp: 5, millis: None, code: Some(42), message: String::from(message), checksum: None, fields: BTreeMap::new() };
+        assert_eq!(entry, entry_from_json(&entry.to_json()).unwrap());
+
+        // unversioned and current objects, unknown keys ignored
+        let old = entry_from_json(r#"{"timestamp":7,"message":"from before \ud83d\ude00"}"#).unwrap();
+        assert_eq!((7, None, "from before \u{1F600}"), (old.timestamp, old.code, old.message.as_str()));
+        let newer = entry_from_json(r#"{ "v": 1, "timestamp": 7, "message": "x", "host": "a" }"#).unwrap();
+        assert_eq!(Some("a"), newer.fields.get("host").map(String::as_str));
+
+        match entry_from_json(r#"{"v":2,"timestamp":7,"message":"x"}"#) {
+            Err(e) => assert!(e.is::<SchemaVersionError>()),
+            Ok(_) => panic!("A future schema version should have been refused."),
+        }
+        for bad in [&malformed_to_json("junk"), r#"{"v":1,"timestamp":"7","message":"x"}"#, r#"{"v":1,"timestamp":7}"#, "[]", r#"{"v":1,"timestamp":7,"message":"x"} trailing"#] {
+            match entry_from_json(bad) {
+                Err(e) => assert!(e.is::<ParseEntryError>(), "{}", bad),
+                Ok(_) => panic!("{} should not have parsed.", bad),
+            }
+        }
+    }
+
+    #[test]
+    fn schema_version_leads_every_object() {
+        let entry = LogEntry { timestamp: 5, millis: None, code: None, message: String::from("x"), checksum: None, fields: BTreeMap::new() };
+        let version = (String::from("v"), Value::Num(JSON_SCHEMA_VERSION.to_string()));
+        assert_eq!(version, decode_object(&entry.to_json())[0]);
+        assert_eq!(version, decode_object(&malformed_to_json("junk"))[0]);
+    }
+}
@@ -6,19 +6,97 @@
 //! not rely on 3rd-party dependencies while still offering
 //! the high-level convience needed from this tool.
 
+use std::borrow::Cow;
 use std::error::Error;
 use std::path::{PathBuf, Path};
 use std::fs;
+use std::io::Write;
 use std::time::SystemTime;
 
+mod atomic;
+mod date;
 mod error;
+mod export;
+mod filesystem;
+mod html;
+mod info;
+mod integrity;
+mod json;
+mod lock;
+mod logfmt;
+mod logger;
+#[cfg(any(test, feature = "test-util"))]
+mod memfs;
+mod merge;
+mod poll;
+pub mod prelude;
+mod reader;
+mod sink;
+mod stats;
+mod syslog;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_support;
+
+pub use date::{civil_from_days, days_from_civil, Date};
+pub use error::{BadExtensionError, ConfigError, FieldNameError, FileNameError, IoContext, NotWritableError, ParseEntryError, SamePathError, SchemaVersionError, SinkError, SocketNotFoundError, TimeoutError, into_io_error};
+pub use export::{export_html, export_json, export_logfmt, export_syslog, ExportReport};
+pub use filesystem::{FileMetadata, FileSystem, RealFileSystem};
+#[cfg(any(test, feature = "test-util"))]
+pub use memfs::{MemoryFileSystem, Operation};
+pub use info::{disk_usage_report, info, LogInfo};
+pub use json::JSON_SCHEMA_VERSION;
+pub use lock::{FileLock, LockOptions};
+pub use integrity::{
+    check_monotonic, compact_log, repair, validate, verify, verify_chained, Checksums, Problem, RepairReport, TornLineFix,
+    ValidationReport, VerifyReport,
+};
+pub use logger::{BacktraceMode, Diagnostics, Heartbeat, Logger, OutputFormat, Scope};
+pub use merge::{merge, merge_with, MalformedLines, MergeOptions, MergeReport};
+pub use poll::{entries_from_offset, entries_since, follow, Follow, Poll, StopHandle, DEFAULT_FOLLOW_INTERVAL};
+pub use reader::{entries, entries_json, entries_with_terminator, head, index_entries, read_entries, read_entries_json, read_entry_at, Entries, FORMAT_VERSION, JsonEntries, LogEntry, Record, TakeEntries};
+pub use sink::{FileSink, MemorySink, RingSink, Sink, StdoutSink, SyslogUdpSink, TcpSink};
+#[cfg(unix)]
+pub use sink::{UnixSink, UnixSocketKind};
+pub use stats::{stats, top_messages, write_report, LogStats};
+pub use syslog::SyslogOptions;
+
+/// Width that codes are zero-padded to by `errlog_coded`.
+pub const DEFAULT_CODE_WIDTH: usize = 4;
 
 /// You must specify the file name within the path. In it's current state,
 /// only a single new directory can be created. If you are placing error logs
 /// outside of the root of the project, it's recommended to use an absolute
 /// file path. Timestamps on error log are in relation to `UNIX_EPOCH`. This is
 /// a change for the future but will take *time* to implement. Get it?
+///
+/// Called again while this thread is already writing an entry, for
+/// instance from a panic hook that fires during a write, it writes nothing
+/// and returns `Ok`.
 pub fn errlog(path: &str, error: String) -> Result<(), Box<dyn Error>> {
+    errlog_returning(path, &error)?;
+    Ok(())
+}
+
+/// Same as `errlog` with the file and line this is called from appended as
+/// ` location=<file>:<line>`, like the field `Logger::log_here` adds. A
+/// helper wrapping this needs `#[track_caller]` too to pass on its own
+/// caller's location, as does anything wrapping that helper.
+#[track_caller]
+pub fn errlog_here(path: &str, error: String) -> Result<(), Box<dyn Error>> {
+    let caller = std::panic::Location::caller();
+    errlog(path, logger::with_fields(&error, &[logger::location_field(caller)]))
+}
+
+/// Same as `errlog`, returning the line that was written exactly as it is
+/// in the file, e.g. `1690999212 - disk full`, without the terminators
+/// around it, so it can be echoed elsewhere without formatting it again.
+/// A message with line breaks comes back with its continuation lines. A
+/// nested call that `errlog` would skip writes nothing and returns an
+/// empty string.
+pub fn errlog_returning(path: &str, error: &str) -> Result<String, Box<dyn Error>> {
+    let Some(_writing) = logger::enter_write(error) else {
+        return Ok(String::new());
+    };
 
     let path = create_path_from_str(path)?;
 
@@ -26,18 +104,129 @@ pub fn errlog(path: &str, error: String) -> Result<(), Box<dyn Error>> {
 
     check_or_make_log(&path)?;
 
-    append_log(&path, error.as_str())?;
-    Ok(())
+    append_log(&path, error, b"\n")
+}
+
+/// The line `errlog` would write for `error` right now, without writing
+/// it, e.g. `1690999212 - disk full`, in the same form `errlog_returning`
+/// gives back. See `Logger::render` for a logger's own settings and a
+/// given time.
+pub fn format_entry(error: &str) -> String {
+    let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+    format_at(now.as_secs(), error, b"\n")
+}
+
+/// Same as `errlog` but tags the entry with a numeric code, zero-padded to
+/// `DEFAULT_CODE_WIDTH` digits: `<timestamp> - [E0042] - <error>`.
+pub fn errlog_coded(path: &str, code: u32, error: String) -> Result<(), Box<dyn Error>> {
+    errlog_coded_width(path, code, DEFAULT_CODE_WIDTH, error)
 }
 
+/// Same as `errlog_coded` with the code zero-padded to `width` digits.
+/// Codes longer than `width` are written in full.
+pub fn errlog_coded_width(path: &str, code: u32, width: usize, error: String) -> Result<(), Box<dyn Error>> {
+    errlog(path, format!("[E{:0width$}] - {}", code, error, width = width))
+}
+
+/// What `errlog_raw_with` does with a newline inside the line it's given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RawNewlines {
+    /// Fail with `io::ErrorKind::InvalidInput` and write nothing.
+    #[default]
+    Reject,
+    /// Write each newline as the two characters `\n`, keeping the line one
+    /// record. Text that already has a literal `\n` in it then reads back
+    /// the same, so this can't be undone exactly.
+    Escape,
+}
+
+/// Appends `line` exactly as given, with the same path checks and record
+/// layout as `errlog` but without a timestamp, for callers that bring a
+/// format of their own, e.g. an upstream event's original time. Readers
+/// report such lines as `Record::Malformed` unless they happen to look like
+/// entries. Fails with `io::ErrorKind::InvalidInput` if `line` contains a
+/// newline, which would split it into several records; see
+/// `errlog_raw_with` for escaping it instead.
+pub fn errlog_raw(path: &str, line: &str) -> Result<(), Box<dyn Error>> {
+    errlog_raw_with(path, line, RawNewlines::Reject)
+}
+
+/// Same as `errlog_raw` with `newlines` deciding what happens to a newline
+/// inside `line`.
+pub fn errlog_raw_with(path: &str, line: &str, newlines: RawNewlines) -> Result<(), Box<dyn Error>> {
+    let line = match newlines {
+        _ if !line.contains('\n') => Cow::Borrowed(line),
+        RawNewlines::Reject => {
+            return Err(Box::new(std::io::Error::new(std::io::ErrorKind::InvalidInput, "raw log line must not contain a newline")));
+        }
+        RawNewlines::Escape => Cow::Owned(line.replace('\n', "\\n")),
+    };
+    let Some(_writing) = logger::enter_write(&line) else {
+        return Ok(());
+    };
+
+    let path = create_path_from_str(path)?;
+    check_or_make_directory(&path)?;
+    check_or_make_log(&path)?;
+    append_line(&path, &line, b"\n")
+}
+
+/// Appends `\n<seconds since UNIX_EPOCH> - <message>\n` to `path` with as
+/// little machinery as possible, for panic hooks and other last-gasp
+/// handlers. The file is opened with append and create and written once.
+/// Nothing else runs: no path checks, no directory creation, no
+/// continuation lines, checksums or other `Logger` settings, and no locks,
+/// so it can't block on a logger that's stuck mid-write.
+///
+/// That also means it may interleave with entries other writers are
+/// appending at the same moment, and a `Logger` sharing the file doesn't
+/// know about the entry. Errors are returned but nothing is logged about
+/// them, callers in a crash path are free to ignore them.
+pub fn emergency_write(path: &str, message: &str) -> std::io::Result<()> {
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let mut line = Vec::with_capacity(message.len() + 24);
+    line.push(b'\n');
+    line.extend_from_slice(secs.to_string().as_bytes());
+    line.extend_from_slice(b" - ");
+    line.extend_from_slice(message.as_bytes());
+    line.push(b'\n');
+
+    fs::OpenOptions::new().append(true).create(true).open(path)?.write_all(&line)
+}
+
+/// Whether a log exists at `path`. Paths that `errlog` would reject, such
+/// as ones without a `.log` extension, never exist.
+pub fn log_exists(path: &str) -> bool {
+    match create_path_from_str(path) {
+        Ok(path) => path.is_file(),
+        Err(_) => false,
+    }
+}
+
+/// Size in bytes of the log at `path`, after the same path checks as
+/// `errlog`.
+pub fn log_size(path: &str) -> Result<u64, Box<dyn Error>> {
+    let path = create_path_from_str(path)?;
+    Ok(fs::metadata(&path).map_err(IoContext::wrap("read metadata of", &path))?.len())
+}
+
+/// Also drops `.` components and repeated separators, so `./logs//app.log`
+/// and `./logs/./app.log` both become `./logs/app.log`. `..` is kept, since
+/// resolving it needs the filesystem. Never panics: any `text` is either a
+/// path or a `BadExtensionError`.
 fn create_path_from_str(text: &str) -> Result<PathBuf, Box<dyn Error>> {
-    let path = PathBuf::from(text);
+    let path: PathBuf = Path::new(text).components().collect();
     if let Some(ext) = path.extension() {
         if ext != "log" {
-            return Err(Box::new(error::BadExtensionError {message: String::from("must use \".log\" extension in file name")}));
+            return Err(Box::new(error::BadExtensionError {
+                message: String::from("must use \".log\" extension in file name"),
+                found: Some(ext.to_string_lossy().into_owned()),
+            }));
         }
     } else {
-        return Err(Box::new(error::BadExtensionError {message: String::from("must use \".log\" extension in file name")}));
+        return Err(Box::new(error::BadExtensionError {message: String::from("must use \".log\" extension in file name"), found: None}));
     }
 
     Ok(path)
@@ -51,37 +240,86 @@ fn check_or_make_directory(path: &Path) -> Result<(), Box<dyn Error>> {
         return Ok(());
     }
 
-    fs::create_dir(dir_path)?;
+    fs::create_dir(&dir_path).map_err(IoContext::wrap("create directory", &dir_path))?;
     Ok(())    
 }
 
+/// Creating without truncating means a writer racing another one to create
+/// the file can't wipe what the other just appended.
 fn check_or_make_log(path: &Path) -> Result<(), Box<dyn Error>> {
     let path = path.to_path_buf();
-    if path.try_exists()? {
+    if path.try_exists().map_err(IoContext::wrap("look for", &path))? {
         Ok(())
     } else {
-        fs::File::create(path)?;
+        fs::OpenOptions::new().create(true).append(true).open(&path).map_err(IoContext::wrap("create", &path))?;
         Ok(())
     }
 }
 
-fn append_log(file_path: &PathBuf, error: &str) -> Result<(), Box<dyn Error>> {
+fn append_log(file_path: &PathBuf, error: &str, terminator: &[u8]) -> Result<String, Box<dyn Error>> {
 
+    let line = format_now(error, terminator)?;
+    append_line(file_path, &line, terminator)?;
+    Ok(line)
+}
+
+/// `<seconds since UNIX_EPOCH> - <error>`, the line `append_log` writes.
+/// With a newline `terminator`, line breaks in `error` become continuation
+/// lines; other terminators keep them as they are.
+fn format_now(error: &str, terminator: &[u8]) -> Result<String, Box<dyn Error>> {
     let date_in_sec = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)?
             .as_secs();
 
-    let current_log = fs::read_to_string(file_path)?;
+    Ok(format_at(date_in_sec, error, terminator))
+}
 
-    let updated_log = format!("{}\n{} - {}\n", current_log, date_in_sec, error);
+/// Same as `format_now` with a timestamp taken earlier.
+fn format_at(date_in_sec: impl std::fmt::Display, error: &str, terminator: &[u8]) -> String {
+    if terminator == b"\n" {
+        format!("{} - {}", date_in_sec, reader::encode_message(error))
+    } else {
+        format!("{} - {}", date_in_sec, error)
+    }
+}
 
-    fs::write(file_path, updated_log)?;
+/// Appends `line` as its own record, in the same layout as `append_log`.
+/// The file is opened in append mode rather than rewritten, so readers
+/// following it never see it shrink.
+///
+/// The whole record goes out in a single `write_all` on that handle, so
+/// with `O_APPEND` it lands as one piece even when other processes append
+/// at the same time. Should the OS accept only part of it, `write_all`
+/// writes the rest separately and another writer could in theory get in
+/// between; one contiguous buffer keeps that to the rare short write.
+fn append_line(file_path: &PathBuf, line: &str, terminator: &[u8]) -> Result<(), Box<dyn Error>> {
+    let mut file = fs::OpenOptions::new().append(true).open(file_path).map_err(IoContext::wrap("open", file_path))?;
+    file.write_all(&record(line, terminator)).map_err(IoContext::wrap("append to", file_path))?;
     Ok(())
 }
 
+/// `line` surrounded by `terminator`, the bytes `append_line` writes.
+fn record(line: &str, terminator: &[u8]) -> Vec<u8> {
+    let mut record = Vec::with_capacity(line.len() + 2 * terminator.len());
+    record.extend_from_slice(terminator);
+    record.extend_from_slice(line.as_bytes());
+    record.extend_from_slice(terminator);
+    record
+}
+
+/// Whether `a` and `b` name the same existing file. `a` must exist.
+fn is_same_file(a: &str, b: &str) -> Result<bool, Box<dyn Error>> {
+    if !Path::new(b).try_exists()? {
+        return Ok(false);
+    }
+    Ok(fs::canonicalize(a)? == fs::canonicalize(b)?)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_support::TempLogDir;
+    use std::io;
 
     #[test]
     fn test_create_file_path() {
@@ -92,92 +330,224 @@ mod tests {
     }
 
 
+    #[test]
+    fn bad_extension_names_what_was_given() {
+        match create_path_from_str("./logs/app.txt") {
+            Err(e) => match e.downcast_ref::<BadExtensionError>() {
+                Some(bad) => {
+                    assert_eq!(Some("txt"), bad.found.as_deref());
+                    assert!(e.to_string().contains("txt"));
+                }
+                None => panic!("Expected a BadExtensionError, got {}", e),
+            },
+            Ok(path) => panic!("A .txt log should have been refused, got {}", path.display()),
+        }
+
+        match create_path_from_str("./logs/app") {
+            Err(e) => assert_eq!("Extension must end with \".log\", got none", e.to_string()),
+            Ok(path) => panic!("A log without an extension should have been refused, got {}", path.display()),
+        }
+    }
+
+
+    #[test]
+    fn test_normalize_file_path() {
+        assert_eq!(PathBuf::from("./logs/app.log"), create_path_from_str("./logs//app.log").unwrap());
+        assert_eq!(PathBuf::from("./logs/app.log"), create_path_from_str("./logs/./app.log").unwrap());
+        assert_eq!(PathBuf::from("logs/../app.log"), create_path_from_str("logs/.//../app.log").unwrap());
+        assert_eq!(PathBuf::from("/var/log/app.log"), create_path_from_str("//var///log/app.log").unwrap());
+    }
+
+
     #[test]
     fn test_directory_checks() {
-        let path = PathBuf::from("./test-data/test.log");
+        let dir = TempLogDir::new();
+        let path = dir.path().join("test.log");
         if let Err(e) =  check_or_make_directory(&path) {
-            assert!(false, "Could not test for `./test-data` directory. Error: {e}");
+            panic!("Could not test for `{}` directory. Error: {e}", dir.path().display());
         }
 
-        let mut path = PathBuf::from("./new-dir/test.log");
+        let path = dir.path().join("new-dir/test.log");
         if let Err(e) = check_or_make_directory(&path) {
-            assert!(false, "Could not create `./new-dir/` directory. Error: {e}");
+            panic!("Could not create `new-dir/` directory. Error: {e}");
         }
-
-        // cleanup
-        path.pop();
-        fs::remove_dir(&path).unwrap();
+        assert!(dir.path().join("new-dir").is_dir());
 
         let path = PathBuf::from("./test.log");
         if let Err(e) =  check_or_make_directory(&path) {
-            assert!(false, "Failed to check current directory. Error: {e}");
+            panic!("Failed to check current directory. Error: {e}");
         }
-
-        assert!(true);
     }
 
 
     #[test]
     fn test_create_log() {
-        let path = PathBuf::from("./test-data/new-file.txt");
+        let dir = TempLogDir::new();
+        let path = dir.path().join("new-file.txt");
         if let Err(e) = check_or_make_log(&path) {
-            assert!(false, "Failed to create file `./test-data/new-file.txt`. Error: {e}");
+            panic!("Failed to create file `{}`. Error: {e}", path.display());
         }
-
-        // clean up
-        fs::remove_file(&path).unwrap();
-
-        assert!(true);
     }
 
 
     #[test]
     fn test_append() {
-        let path = PathBuf::from("./test-data/test.log");
-        if let Err(e) =  append_log(&path, "test error") {
-            assert!(false, "Could not write contents to `./test-data/test.log`. Error: {e}");
+        let dir = TempLogDir::new();
+        let path = dir.path().join("test.log");
+        fs::write(&path, "").unwrap();
+        if let Err(e) =  append_log(&path, "test error", b"\n") {
+            panic!("Could not write contents to `{}`. Error: {e}", path.display());
         }
 
-        let bad_path = PathBuf::from("./test-data/does-not-exist.log");
-        if let Ok(_) =  append_log(&bad_path, "Something") {
-            assert!(false, "Should not be able to write contents to `./test-data/does-not-exist.log`.");
+        let bad_path = dir.path().join("does-not-exist.log");
+        if append_log(&bad_path, "Something", b"\n").is_ok() {
+            panic!("Should not be able to write contents to `{}`.", bad_path.display());
         }
+    }
 
-        // clean up
-        fs::write("./test-data/test.log", "").unwrap();
 
-        assert!(true);
+    #[test]
+    fn errors_convert_into_io_errors() {
+        fn as_io(path: &str) -> io::Result<()> {
+            errlog(path, String::from("x")).map_err(into_io_error)
+        }
+
+        match as_io("./test-data/errors.txt") {
+            Err(e) => {
+                assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+                assert_eq!("Extension must end with \".log\", got \".txt\"", e.to_string());
+                assert!(e.get_ref().is_some_and(|inner| inner.is::<BadExtensionError>()));
+            }
+            Ok(_) => panic!("A .txt log should have been refused."),
+        }
+
+        let timeout = io::Error::from(TimeoutError { message: String::from("too slow") });
+        assert_eq!((io::ErrorKind::TimedOut, String::from("too slow")), (timeout.kind(), timeout.to_string()));
+        let context = into_io_error(Box::new(IoContext::wrap("open", Path::new("a.log"))(io::Error::from(io::ErrorKind::NotFound))));
+        assert_eq!(io::ErrorKind::NotFound, context.kind());
+        assert!(context.to_string().starts_with("failed to open a.log: "));
     }
 
+    #[test]
+    fn io_errors_name_the_path_and_operation() {
+        let memory = std::sync::Arc::new(MemoryFileSystem::new());
+        let logger = Logger::new_with_file_system("./mock-logs/errors.log", memory.clone()).unwrap();
+        memory.fail(Operation::OpenAppend, io::ErrorKind::PermissionDenied);
+        match logger.log(String::from("denied")) {
+            Ok(_) => panic!("Opening a log the file system refuses should have failed."),
+            Err(e) => {
+                let context = e.downcast_ref::<IoContext>().expect("an IoContext");
+                assert_eq!("failed to open ./mock-logs/errors.log: scripted OpenAppend failure", context.to_string());
+                let source = context.source().and_then(|source| source.downcast_ref::<io::Error>()).expect("an io::Error source");
+                assert_eq!(io::ErrorKind::PermissionDenied, source.kind());
+            }
+        }
+    }
 
     #[test]
-    fn errlog_success() {
-        if let Err(e) =  errlog("./test-data/errlog-unit-test.log", String::from("error log was successful")) {
-            assert!(false, "Something went horribly wrong. Error: {e}");
+    fn concurrent_appends_do_not_interleave() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("errlog-interleave-test.log");
+        std::thread::scope(|scope| {
+            for fill in ['a', 'b'] {
+                scope.spawn(move || {
+                    let message = fill.to_string().repeat(64 * 1024);
+                    for _ in 0..20 {
+                        errlog(path, message.clone()).unwrap();
+                    }
+                });
+            }
+        });
+
+        let contents = fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+        assert_eq!(40, lines.len());
+        for line in lines {
+            let (_, message) = line.split_once(" - ").unwrap();
+            assert_eq!(64 * 1024, message.len());
+            assert!(message.bytes().all(|b| b == b'a') || message.bytes().all(|b| b == b'b'));
         }
+    }
 
-        if let Err(e) = errlog("./new-folder/errlog-unit-test.log", String::from("error log was successful")) {
-            assert!(false, "Something went horribly wrong. Error: {e}");
+
+    #[test]
+    fn every_append_leaves_one_trailing_newline() {
+        fn assert_one_newline(path: &str) {
+            let contents = fs::read_to_string(path).unwrap();
+            assert!(contents.ends_with('\n') && !contents.ends_with("\n\n"), "{:?}", contents);
         }
 
-        // clean up
-        fs::remove_file("./new-folder/errlog-unit-test.log").unwrap();
-        fs::remove_dir("./new-folder").unwrap();
+        let dir = TempLogDir::new();
+        for (name, before) in [("missing.log", None), ("unterminated.log", Some("by hand")), ("blank-tail.log", Some("\n1 - x\n\n\n"))] {
+            let path = &dir.file(name);
+            if let Some(before) = before {
+                fs::write(path, before).unwrap();
+            }
+
+            errlog(path, String::from("plain")).unwrap();
+            assert_one_newline(path);
+            errlog(path, String::from("ends in a newline\n")).unwrap();
+            assert_one_newline(path);
+            errlog_coded(path, 42, String::from("coded")).unwrap();
+            assert_one_newline(path);
+            errlog_raw_with(path, "raw\nline", RawNewlines::Escape).unwrap();
+            assert_one_newline(path);
+
+            let logger = logger::Logger::new(path).unwrap().with_run_separator(true);
+            logger.log(String::from("through a logger\n\n")).unwrap();
+            assert_one_newline(path);
+            drop(logger);
+            let logger = logger::Logger::new(path).unwrap().with_prepend(true);
+            logger.log(String::from("prepended")).unwrap();
+            assert_one_newline(path);
+        }
 
-        assert!(true);
+        // prepending keeps an old tail, so it's the one that needs fixing
+        let path = &dir.file("prepend-unterminated.log");
+        fs::write(path, "\n1 - by hand").unwrap();
+        logger::Logger::new(path).unwrap().with_prepend(true).log(String::from("newest")).unwrap();
+        assert_one_newline(path);
+        assert!(fs::read_to_string(path).unwrap().ends_with("\n1 - by hand\n"));
     }
 
 
     #[test]
-    fn errlog_fail_bad_path() {
-        if let Ok(_) = errlog("./no-folder/abcd/errlog-unit-test.log", String::from("error log should fail")) {
-            assert!(false, "`./no-folder/abcd/errlog-unit-test.log` should have failed as a bad path.");
+    fn errlog_coded_round_trip() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("errlog-coded-test.log");
+        if let Err(e) = errlog_coded(path, 42, String::from("disk full")) {
+            panic!("Could not write coded entry. Error: {e}");
         }
+        errlog_coded_width(path, 7, 2, String::from("short code")).unwrap();
+        errlog_coded_width(path, 12345, 2, String::from("long code")).unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+        assert!(contents.contains(" - [E0042] - disk full\n"));
+        assert!(contents.contains(" - [E07] - short code\n"));
+        assert!(contents.contains(" - [E12345] - long code\n"));
+
+        let entries = read_entries(path).unwrap();
+        assert_eq!(3, entries.len());
+        assert_eq!(Some(42), entries[0].code);
+        assert_eq!("disk full", entries[0].message);
+        assert_eq!(Some(7), entries[1].code);
+        assert_eq!(Some(12345), entries[2].code);
+    }
 
-        if let Ok(_) = errlog("./test-data/errlog-unit-test", String::from("error log should fail")) {
-            assert!(false, "not specifying a `.log` file should fail.");
-        }
 
-        assert!(true);
+    #[test]
+    fn log_exists_and_size() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("log-size-test.log");
+        assert!(!log_exists(path));
+        assert!(log_size(path).is_err());
+
+        fs::write(path, "0123456789").unwrap();
+        assert!(log_exists(path));
+        assert_eq!(10, log_size(path).unwrap());
+
+        // the extension is checked before the filesystem
+        assert!(!log_exists("./test-data"));
+        assert!(log_size("./Cargo.toml").is_err());
     }
 }
@@ -0,0 +1,203 @@
+//! A cooperative lock between processes sharing one log, held by creating
+//! `<log>.lock` and released by removing it.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How `FileLock::acquire_with` waits for and breaks locks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockOptions {
+    /// A lock file older than this whose holder is gone may be broken.
+    pub stale_after: Duration,
+    /// Longest wait between attempts; the wait doubles from 1ms up to this.
+    pub max_backoff: Duration,
+    /// Give up with an `io::ErrorKind::TimedOut` error once this much time
+    /// has passed without getting the lock. `None` waits for as long as it
+    /// takes.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for LockOptions {
+    fn default() -> LockOptions {
+        LockOptions {
+            stale_after: Duration::from_secs(30),
+            max_backoff: Duration::from_millis(50),
+            timeout: None,
+        }
+    }
+}
+
+/// Holds the lock on a log until dropped. The lock file records the
+/// holder's pid and the time it was taken, as `<pid> <seconds>`.
+///
+/// Only code that takes the lock is kept out, and breaking a stale lock
+/// is best effort: two processes giving up on the same stale lock at the
+/// same moment can both end up holding it.
+#[derive(Debug)]
+pub struct FileLock {
+    path: PathBuf,
+}
+
+impl FileLock {
+    /// Waits until the lock on `log` is free and takes it, using the
+    /// default `LockOptions`.
+    pub fn acquire(log: &Path) -> io::Result<FileLock> {
+        FileLock::acquire_with(log, LockOptions::default())
+    }
+
+    /// Waits until the lock on `log` is free and takes it. Attempts back off
+    /// exponentially up to `options.max_backoff`, and a stale lock file is
+    /// removed when its holder looks gone: on Linux when `/proc/<pid>` no
+    /// longer exists, elsewhere by age alone.
+    pub fn acquire_with(log: &Path, options: LockOptions) -> io::Result<FileLock> {
+        let mut path = log.as_os_str().to_owned();
+        path.push(".lock");
+        let path = PathBuf::from(path);
+
+        let started = Instant::now();
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            match fs::OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    let lock = FileLock { path };
+                    writeln!(file, "{} {}", std::process::id(), now_secs())?;
+                    return Ok(lock);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(e) => return Err(e),
+            }
+
+            if break_if_stale(&path, options.stale_after) {
+                continue;
+            }
+            let mut wait = backoff;
+            if let Some(timeout) = options.timeout {
+                let left = timeout.saturating_sub(started.elapsed());
+                if left.is_zero() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("timed out after {:?} waiting for {}", timeout, path.display()),
+                    ));
+                }
+                wait = wait.min(left);
+            }
+            thread::sleep(wait);
+            backoff = (backoff * 2).min(options.max_backoff);
+        }
+    }
+
+    /// The lock file, `<log>.lock`.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// Removes the lock file at `path` if it's older than `stale_after` and
+/// its holder isn't running. The age comes from the recorded time, or the
+/// file's modification time while the holder hasn't written it yet.
+fn break_if_stale(path: &Path, stale_after: Duration) -> bool {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return false;
+    };
+    let mut fields = contents.split_whitespace().map(|field| field.parse::<u64>().ok());
+    let (pid, taken) = (fields.next().flatten(), fields.next().flatten());
+
+    let age = match taken {
+        Some(taken) => Duration::from_secs(now_secs().saturating_sub(taken)),
+        None => match fs::metadata(path).and_then(|meta| meta.modified()) {
+            Ok(modified) => modified.elapsed().unwrap_or_default(),
+            Err(_) => return false,
+        },
+    };
+    if age <= stale_after || pid.is_some_and(is_running) {
+        return false;
+    }
+
+    // only remove the lock that was judged stale, not a fresh one
+    match fs::read_to_string(path) {
+        Ok(again) if again == contents => fs::remove_file(path).is_ok(),
+        _ => false,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn is_running(pid: u64) -> bool {
+    Path::new(&format!("/proc/{}", pid)).exists()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn is_running(_pid: u64) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempLogDir;
+
+    #[test]
+    fn threads_take_turns() {
+        let dir = TempLogDir::new();
+        let log = &dir.path().join("lock-contention-test.log");
+        let counter = &dir.file("lock-contention-counter.txt");
+        fs::write(counter, "0").unwrap();
+
+        thread::scope(|scope| {
+            for _ in 0..6 {
+                scope.spawn(|| {
+                    for _ in 0..10 {
+                        let lock = FileLock::acquire(log).unwrap();
+                        let count: u64 = fs::read_to_string(counter).unwrap().parse().unwrap();
+                        thread::yield_now();
+                        fs::write(counter, (count + 1).to_string()).unwrap();
+                        drop(lock);
+                    }
+                });
+            }
+        });
+
+        assert_eq!("60", fs::read_to_string(counter).unwrap());
+        assert!(!dir.path().join("lock-contention-test.log.lock").exists());
+    }
+
+    #[test]
+    fn stale_lock_is_taken_over() {
+        let dir = TempLogDir::new();
+        let log = dir.path().join("lock-stale-test.log");
+        let lock_path = &dir.file("lock-stale-test.log.lock");
+        let options = LockOptions { stale_after: Duration::from_secs(60), ..LockOptions::default() };
+
+        // no such pid, taken long ago
+        fs::write(lock_path, "4000000000 1000\n").unwrap();
+        let lock = FileLock::acquire_with(&log, options).unwrap();
+        let contents = fs::read_to_string(lock.path()).unwrap();
+        assert_eq!(Some(std::process::id().to_string().as_str()), contents.split_whitespace().next());
+        drop(lock);
+        assert!(!Path::new(lock_path).exists());
+
+        // a recent lock from a dead holder is left alone
+        fs::write(lock_path, format!("4000000000 {}\n", now_secs())).unwrap();
+        assert!(!break_if_stale(Path::new(lock_path), options.stale_after));
+
+        // so is an old one whose holder is still running
+        if cfg!(target_os = "linux") {
+            fs::write(lock_path, format!("{} 1000\n", std::process::id())).unwrap();
+            assert!(!break_if_stale(Path::new(lock_path), options.stale_after));
+        }
+    }
+}
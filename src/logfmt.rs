@@ -0,0 +1,117 @@
+//! logfmt output, one `key=value` line per entry.
+
+use crate::date::rfc3339_fixed;
+use crate::reader::LogEntry;
+
+/// Appends `key` to `out`. Characters that would end a logfmt key early
+/// (spaces, `=`, `"` and control characters) become `_`.
+fn push_key(out: &mut String, key: &str) {
+    for c in key.chars() {
+        if c == ' ' || c == '=' || c == '"' || c.is_control() {
+            out.push('_');
+        } else {
+            out.push(c);
+        }
+    }
+}
+
+/// Appends `value` to `out`, bare when it's a plain word and quoted when
+/// it's empty, contains a space, `"` or `=`, or needs escaping. Inside
+/// quotes `"` and `\` get a backslash, and newlines, tabs and other
+/// control characters are written as `\n`, `\t` or `\u{..}`, so every
+/// entry stays on one line.
+pub(crate) fn push_value(out: &mut String, value: &str) {
+    let needs_quotes = value.is_empty() || value.chars().any(|c| c == ' ' || c == '"' || c == '=' || c == '\\' || c.is_control());
+    if !needs_quotes {
+        out.push_str(value);
+        return;
+    }
+
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{{{:x}}}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// `ts=<RFC 3339> code=<code> msg=<message>` followed by `fields` in the
+/// order given, with `code` left out for entries that don't have one.
+pub(crate) fn entry_to_logfmt(entry: &LogEntry, fields: &[(&str, &str)]) -> String {
+    let mut out = format!("ts={}", rfc3339_fixed(entry.timestamp, entry.millis));
+    if let Some(code) = entry.code {
+        out.push_str(&format!(" code={}", code));
+    }
+    out.push_str(" msg=");
+    push_value(&mut out, &entry.message);
+
+    for (key, value) in fields {
+        out.push(' ');
+        push_key(&mut out, key);
+        out.push('=');
+        push_value(&mut out, value);
+    }
+    out
+}
+
+/// `malformed=<raw line>` for a line that didn't parse as an entry.
+pub(crate) fn malformed_to_logfmt(raw: &str) -> String {
+    let mut out = String::from("malformed=");
+    push_value(&mut out, raw);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn entry(code: Option<u32>, message: &str) -> LogEntry {
+        LogEntry { timestamp: 1_709_820_543, millis: None, code, message: String::from(message), checksum: None, fields: BTreeMap::new() }
+    }
+
+    #[test]
+    fn values_are_quoted_only_when_needed() {
+        assert_eq!("ts=2024-03-07T14:09:03Z msg=refused", entry(None, "refused").to_logfmt());
+        assert_eq!(
+            "ts=2024-03-07T14:09:03Z code=42 msg=\"connection refused\"",
+            entry(Some(42), "connection refused").to_logfmt()
+        );
+        assert_eq!("ts=2024-03-07T14:09:03Z msg=\"a=b\"", entry(None, "a=b").to_logfmt());
+        assert_eq!("ts=2024-03-07T14:09:03Z msg=\"\"", entry(None, "").to_logfmt());
+    }
+
+    #[test]
+    fn quotes_backslashes_and_newlines_are_escaped() {
+        assert_eq!(
+            r#"ts=2024-03-07T14:09:03Z msg="said \"no\" at C:\\tmp\nthen\tleft\u{7}""#,
+            entry(None, "said \"no\" at C:\\tmp\nthen\tleft\u{7}").to_logfmt()
+        );
+        assert_eq!(r#"malformed="torn \"line""#, malformed_to_logfmt("torn \"line"));
+    }
+
+    #[test]
+    fn unicode_is_written_as_is() {
+        assert_eq!("ts=2024-03-07T14:09:03Z msg=Überlauf☃", entry(None, "Überlauf☃").to_logfmt());
+        assert_eq!("ts=2024-03-07T14:09:03Z msg=\"schnee ☃\"", entry(None, "schnee ☃").to_logfmt());
+    }
+
+    #[test]
+    fn extra_fields_follow_the_built_in_keys() {
+        let fields = [("host", "web-1"), ("request id", "a b"), ("empty", "")];
+        assert_eq!(
+            "ts=2024-03-07T14:09:03Z code=7 msg=timeout host=web-1 request_id=\"a b\" empty=\"\"",
+            entry(Some(7), "timeout").to_logfmt_with(&fields)
+        );
+        let mut precise = entry(None, "x");
+        precise.millis = Some(42);
+        assert_eq!("ts=2024-03-07T14:09:03.042Z msg=x", precise.to_logfmt());
+    }
+}
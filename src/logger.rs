@@ -0,0 +1,2805 @@
+//! A reusable handle to a single log file, stdout, or an in-memory ring.
+
+use std::backtrace::{Backtrace, BacktraceStatus};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, VecDeque};
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::panic::Location;
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::atomic::replace_through;
+use crate::date::format_datetime;
+use crate::error::{ConfigError, FieldNameError, IoContext, NotWritableError, SinkError, TimeoutError};
+use crate::filesystem::{FileSystem, RealFileSystem};
+use crate::integrity::{checksum, last_checksum, Checksums};
+use crate::json::ENTRY_KEYS;
+use crate::lock::{FileLock, LockOptions};
+use crate::logfmt::push_value;
+use crate::reader::{entries_with_terminator, Entries};
+use crate::reader::{parse_checksummed_line, parse_line, LogEntry, FORMAT_VERSION};
+use crate::sink::{MemorySink, RingSink, Sink, StdoutSink, SyslogUdpSink};
+
+/// Whether `line` is a boundary written by `Logger::with_run_separator`.
+pub(crate) fn is_run_separator(line: &str) -> bool {
+    line.starts_with("===== run started ") && line.ends_with(" =====")
+}
+
+/// Whether `line` is a marker written by `Logger::with_heartbeat`.
+fn is_heartbeat(line: &str) -> bool {
+    line.starts_with("===== heartbeat ") && line.ends_with(" =====")
+}
+
+/// Whether `line` is one the logger writes between entries, a run
+/// separator or a heartbeat, which readers skip.
+pub(crate) fn is_marker(line: &str) -> bool {
+    is_run_separator(line) || is_heartbeat(line)
+}
+
+fn is_not_writable(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem)
+}
+
+/// `crate::check_or_make_directory` through `file_system`.
+fn make_directory(file_system: &dyn FileSystem, path: &Path) -> io::Result<()> {
+    let mut dir_path = path.to_path_buf();
+    dir_path.pop();
+
+    if file_system.metadata(&dir_path).is_ok_and(|meta| meta.is_dir) {
+        return Ok(());
+    }
+    file_system.create_dir(&dir_path)
+}
+
+/// Opens the log for appending, creating it and its directory if needed,
+/// so a read-only mount or missing permissions fail with a clear error
+/// instead of on the first write.
+fn probe_writable(file_system: &dyn FileSystem, path: &Path) -> Result<(), Box<dyn Error>> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    if let Err(e) = make_directory(file_system, path) {
+        if is_not_writable(&e) {
+            return Err(Box::new(NotWritableError {
+                message: format!("log directory could not be created: {}", dir.display()),
+            }));
+        }
+        return Err(Box::new(e));
+    }
+
+    let existed = file_system.metadata(path).is_ok();
+    match file_system.open_append(path) {
+        Ok(_) => Ok(()),
+        Err(e) if is_not_writable(&e) => {
+            let message = if existed {
+                format!("log file is not writable: {}", path.display())
+            } else {
+                format!("log directory is not writable: {}", dir.display())
+            };
+            Err(Box::new(NotWritableError { message }))
+        }
+        Err(e) => Err(Box::new(e)),
+    }
+}
+
+/// What `Logger::diagnose` found out about a log path.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    /// The directory the log goes in exists.
+    pub dir_exists: bool,
+    /// The directory is missing but `Logger::new` would create it, which it
+    /// only does when the directory above it exists.
+    pub dir_created_would_be: bool,
+    pub file_exists: bool,
+    /// Size in bytes of the existing file.
+    pub file_size: Option<u64>,
+    /// `Logger::new` would be able to open the file for appending.
+    pub writable: bool,
+}
+
+/// The entry `line` is the plain text of, or `error` as it was given when
+/// the line doesn't parse back. `checksummed` lines end in a checksum.
+fn entry_of(line: &str, checksummed: bool, time: Duration, error: &str) -> LogEntry {
+    let entry = if checksummed { parse_checksummed_line(line) } else { parse_line(line) };
+    entry.unwrap_or_else(|| LogEntry {
+        timestamp: time.as_secs(),
+        millis: None,
+        code: None,
+        message: error.to_owned(),
+        checksum: None,
+        fields: BTreeMap::new(),
+    })
+}
+
+/// A `SinkError` like `failed to write to 1 of 3 sinks: sink 2: ..` when
+/// anything `failed`.
+fn sink_result(op: &str, sinks: usize, failed: Vec<String>) -> Result<(), Box<dyn Error>> {
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(Box::new(SinkError { message: format!("failed to {} {} of {} sinks: {}", op, failed.len(), sinks, failed.join("; ")) }))
+}
+
+/// `error` followed by ` key=value` for each of `fields`, with values
+/// quoted as in logfmt when they need it.
+pub(crate) fn with_fields(error: &str, fields: &[(String, String)]) -> String {
+    let mut message = error.to_owned();
+    for (key, value) in fields {
+        message.push(' ');
+        message.push_str(key);
+        message.push('=');
+        push_value(&mut message, value);
+    }
+    message
+}
+
+/// The `location=<file>:<line>` field of `Logger::log_here` and
+/// `crate::errlog_here`.
+pub(crate) fn location_field(caller: &Location) -> (String, String) {
+    (String::from("location"), format!("{}:{}", caller.file(), caller.line()))
+}
+
+/// Refuses a field name that would be hard to tell apart from the message
+/// or the other fields once written, or that a JSON entry already uses for
+/// its own keys.
+fn check_field_name(key: &str) -> Result<(), FieldNameError> {
+    if key.is_empty() || key.chars().any(|c| c.is_whitespace() || c == '=' || c == '"' || c.is_control()) {
+        return Err(FieldNameError {
+            message: format!("field name {:?} must be non-empty without spaces, `=`, `\"` or control characters", key),
+        });
+    }
+    if ENTRY_KEYS.contains(&key) {
+        return Err(FieldNameError { message: format!("field name {:?} is reserved for the entry itself", key) });
+    }
+    Ok(())
+}
+
+/// The message `Logger::with_environment_snapshot` writes, read from the
+/// environment now.
+fn environment_snapshot(version: &str, env_vars: &[String]) -> String {
+    let cwd = match std::env::current_dir() {
+        Ok(cwd) => cwd.display().to_string(),
+        Err(_) => String::from("<unknown>"),
+    };
+    let mut snapshot = String::from("environment");
+    let mut push = |name: &str, value: &str| {
+        snapshot.push('\n');
+        snapshot.push_str(name);
+        snapshot.push_str(": ");
+        snapshot.push_str(&value.replace('\r', "\\r").replace('\n', "\\n"));
+    };
+    push("version", version);
+    push("os", std::env::consts::OS);
+    push("arch", std::env::consts::ARCH);
+    push("cwd", &cwd);
+    for var in env_vars {
+        let value = std::env::var_os(var).map(|value| value.to_string_lossy().into_owned());
+        push(&format!("env {}", var), value.as_deref().unwrap_or("<unset>"));
+    }
+    snapshot
+}
+
+/// What matches of `Logger::with_redaction` are replaced with.
+const REDACTED: &str = "[REDACTED]";
+
+/// One pass of `Logger::with_redaction` or `Logger::with_redactor`.
+enum Redaction {
+    Patterns(Vec<String>),
+    Custom(Box<dyn Fn(&str) -> String + Send + Sync>),
+}
+
+impl fmt::Debug for Redaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Redaction::Patterns(patterns) => f.debug_tuple("Patterns").field(patterns).finish(),
+            Redaction::Custom(_) => f.write_str("Custom(..)"),
+        }
+    }
+}
+
+impl Redaction {
+    fn apply(&self, text: &str) -> String {
+        match self {
+            Redaction::Patterns(patterns) => patterns.iter().fold(text.to_owned(), |text, pattern| redact_pattern(&text, pattern)),
+            Redaction::Custom(redactor) => redactor(text),
+        }
+    }
+}
+
+/// `text` with every match of `pattern` replaced by `REDACTED`. A pattern
+/// ending in `*` matches the rest of it followed by everything up to the
+/// next whitespace or the end; any other pattern matches only itself.
+fn redact_pattern(text: &str, pattern: &str) -> String {
+    let (literal, to_whitespace) = match pattern.strip_suffix('*') {
+        Some(literal) => (literal, true),
+        None => (pattern, false),
+    };
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find(literal) {
+        out.push_str(&rest[..at]);
+        out.push_str(REDACTED);
+        rest = &rest[at + literal.len()..];
+        if to_whitespace {
+            rest = &rest[rest.find(char::is_whitespace).unwrap_or(rest.len())..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// `message` cut into pieces of at most `max` bytes, each ending at a
+/// character boundary. A character longer than `max` gets a piece of its
+/// own.
+fn split_message(message: &str, max: usize) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = message;
+    while !rest.is_empty() {
+        let mut end = max.min(rest.len());
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        if end == 0 {
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        let (part, tail) = rest.split_at(end);
+        parts.push(part);
+        rest = tail;
+    }
+    parts
+}
+
+/// `path` with `.<pid>` inserted before its last extension.
+fn per_process_path(path: &Path, pid: u32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, pid, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, pid),
+    };
+    path.with_file_name(name)
+}
+
+/// Whether a new file can be made in `dir`, by creating and removing an
+/// empty probe file.
+fn can_create_in(dir: &Path) -> bool {
+    let probe = dir.join(format!(".errlog-probe-{}", std::process::id()));
+    match fs::OpenOptions::new().write(true).create_new(true).open(&probe) {
+        Ok(_) => fs::remove_file(&probe).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// The `key=value` pairs `Logger::log_fields` adds to an entry.
+type Fields = Vec<(String, String)>;
+
+/// Entries one thread has logged through `Logger::with_thread_buffers` but
+/// not yet flushed, each with the time it was logged.
+type ThreadBuffer = Arc<Mutex<Vec<(Duration, OutputFormat, String, Fields)>>>;
+
+/// Tells buffering loggers apart in `THREAD_BUFFERS`.
+static NEXT_LOGGER_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// This thread's buffer for each buffering logger it has logged to.
+    static THREAD_BUFFERS: RefCell<Vec<(usize, ThreadBuffer)>> = const { RefCell::new(Vec::new()) };
+
+    /// Set while this thread is writing an entry, see `enter_write`.
+    static WRITING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Marks the current thread as writing an entry until dropped.
+pub(crate) struct WriteGuard;
+
+impl Drop for WriteGuard {
+    fn drop(&mut self) {
+        WRITING.with(|writing| writing.set(false));
+    }
+}
+
+/// `None` when this thread is already writing an entry. That happens when
+/// something that runs during a write, such as a sink's writer, a panic
+/// hook or an allocation error handler, logs again. Writing then would
+/// deadlock on the write lock or recurse forever, so callers skip the
+/// nested entry and only echo it to stderr.
+pub(crate) fn enter_write(error: &str) -> Option<WriteGuard> {
+    if WRITING.with(|writing| writing.replace(true)) {
+        eprintln!("errlog: skipped a nested log call: {}", error);
+        return None;
+    }
+    Some(WriteGuard)
+}
+
+/// Most messages `Logger::with_dedup_window` remembers at once. When more
+/// distinct messages arrive within the window, the oldest are forgotten.
+const DEDUP_CAPACITY: usize = 64;
+
+/// Which entries get a backtrace, see `Logger::capture_backtrace`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BacktraceMode {
+    #[default]
+    Off,
+    /// Entries written by `Logger::log_error`.
+    OnError,
+}
+
+/// How often `Logger::with_heartbeat` writes a heartbeat line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Heartbeat {
+    /// After every `n`th entry.
+    EveryEntries(u64),
+    /// After the first entry logged at least this long after the previous
+    /// heartbeat, or after this logger's first entry. Nothing is written
+    /// while nothing is logged.
+    EveryDuration(Duration),
+}
+
+/// How `Logger::log_as` writes an entry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// The plain `<timestamp> - <message>` line every other write uses.
+    #[default]
+    Text,
+    /// One JSON object in the shape `LogEntry::to_json` gives, on a single
+    /// line. It has no milliseconds or checksum.
+    Json,
+}
+
+/// Bookkeeping that has to change together with the file.
+#[derive(Debug, Default)]
+struct WriteState {
+    /// Hashes of messages written within the dedup window, oldest first,
+    /// with when each was written.
+    recent: VecDeque<(u64, u64)>,
+    separator_pending: bool,
+    /// The version and environment variables for
+    /// `Logger::with_environment_snapshot`, until the snapshot is written.
+    snapshot: Option<(String, Vec<String>)>,
+    /// Set when the logger writes to stdout instead of `Logger::path`.
+    stdout: Option<StdoutSink>,
+    /// Set when the logger keeps entries in memory instead, see
+    /// `Logger::to_ring_sink`.
+    ring: Option<RingSink>,
+    /// Set when the logger writes to a handle it was given instead, see
+    /// `Logger::from_file`.
+    handle: Option<fs::File>,
+    /// Sinks every entry also goes to, see `Logger::also_sink`.
+    sinks: Vec<Box<dyn Sink>>,
+    /// Checksum of the last line for `Checksums::Chained`, `None` until
+    /// it has been picked up from the file.
+    chain: Option<Option<u32>>,
+    /// Second of the entry written last, for `with_millis_on_collision`.
+    last_second: Option<u64>,
+    /// Entries logged so far, and the time of the last heartbeat or of the
+    /// first entry, for `Logger::with_heartbeat`.
+    logged: u64,
+    last_heartbeat: Option<Duration>,
+}
+
+impl WriteState {
+    /// Whether the message hashing to `hash` was remembered within `window`
+    /// seconds before `timestamp`.
+    fn seen(&mut self, timestamp: u64, hash: u64, window: u64) -> bool {
+        while self.recent.front().is_some_and(|&(_, seen)| seen.saturating_add(window) <= timestamp) {
+            self.recent.pop_front();
+        }
+        self.recent.iter().any(|&(seen, _)| seen == hash)
+    }
+
+    /// Remembers a message hashing to `hash` as written at `timestamp`.
+    fn remember(&mut self, hash: u64, timestamp: u64) {
+        if self.recent.len() == DEDUP_CAPACITY {
+            self.recent.pop_front();
+        }
+        self.recent.push_back((hash, timestamp));
+    }
+}
+
+/// Writes entries to one log file, validating the path once up front
+/// instead of on every call like `errlog` does.
+///
+/// A `Logger` is a cheap handle: clones share the same file, lock and
+/// settings, so one logger can be handed to many threads. Writes aren't
+/// buffered, so dropping a clone, or the last one, leaves nothing unwritten.
+///
+/// Threads logging through one logger or its clones get this guarantee:
+/// every entry that `log` returns `Ok` for is in the file exactly once, as
+/// a whole record that parses back, never interleaved with another entry.
+/// Entries are written one at a time under the logger's lock, each with a
+/// single `write_all`. Separate loggers for the same file, and other
+/// processes, only get what `O_APPEND` gives; see `cross_process_lock`.
+///
+/// The builder methods, which take the logger and hand it back, are for
+/// setting it up before it's cloned. On a clone they change nothing, and
+/// `build` fails with `ConfigError`.
+#[derive(Debug, Clone)]
+pub struct Logger {
+    inner: Arc<Inner>,
+    /// The first builder method called after this logger was cloned, which
+    /// `build` turns into a `ConfigError`.
+    configured_late: Option<&'static str>,
+}
+
+/// Everything shared between clones of a `Logger`.
+#[derive(Debug)]
+struct Inner {
+    path: Option<PathBuf>,
+    terminator: Vec<u8>,
+    checksums: Option<Checksums>,
+    cross_process_lock: bool,
+    prepend: bool,
+    millis_on_collision: bool,
+    /// Whether `log_error` adds the error's `Debug` form.
+    error_debug: bool,
+    backtraces: BacktraceMode,
+    heartbeat: Option<Heartbeat>,
+    started: Instant,
+    /// `started` on the wall clock, for the run separator.
+    started_at: Duration,
+    /// Applied in order to every message and field value.
+    redactions: Vec<Redaction>,
+    /// Longest message written as one entry, see `with_max_message_len`.
+    max_message_len: Option<usize>,
+    syslog_udp: Option<SyslogUdpSink>,
+    dedup_window: Option<Duration>,
+    lock_timeout: Option<Duration>,
+    drop_on_timeout: bool,
+    /// Entries dropped by `try_log` or after `lock_timeout`, in total and
+    /// since the last "entries dropped" entry.
+    dropped: AtomicU64,
+    unreported: AtomicU64,
+    file_system: Arc<dyn FileSystem>,
+    /// `Logger::new` made the file rather than finding it.
+    created_file: bool,
+    had_content: bool,
+    enabled: AtomicBool,
+    /// Every thread's buffer, set by `Logger::with_thread_buffers`.
+    thread_buffers: Option<(usize, Mutex<Vec<ThreadBuffer>>)>,
+    write_lock: Mutex<WriteState>,
+    last_error: Mutex<Option<String>>,
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        let _ = self.flush_buffers();
+    }
+}
+
+impl Logger {
+    /// Validates `path` and creates its directory and file if needed, with
+    /// the same rules as `errlog`. Fails with `NotWritableError` right away
+    /// if the file can't be opened for writing.
+    pub fn new(path: &str) -> Result<Logger, Box<dyn Error>> {
+        Logger::new_with_file_system(path, Arc::new(RealFileSystem))
+    }
+
+    /// Same as `Logger::new`, but the directory and file are created,
+    /// written and renamed through `file_system` instead of on the disk,
+    /// e.g. to simulate failures in tests with `MemoryFileSystem` from the
+    /// `test-util` feature. `FileLock`s, reading a chained log's last
+    /// checksum and `Logger::entries` still use the disk.
+    pub fn new_with_file_system(path: &str, file_system: Arc<dyn FileSystem>) -> Result<Logger, Box<dyn Error>> {
+        let path = crate::create_path_from_str(path)?;
+        let existing = file_system.metadata(&path).ok();
+        let had_content = existing.is_some_and(|meta| meta.len > 0);
+        probe_writable(&*file_system, &path)?;
+
+        Ok(Logger {
+            inner: Arc::new(Inner {
+                path: Some(path),
+                terminator: b"\n".to_vec(),
+                checksums: None,
+                cross_process_lock: false,
+                prepend: false,
+                millis_on_collision: false,
+                error_debug: false,
+                backtraces: BacktraceMode::Off,
+                heartbeat: None,
+                started: Instant::now(),
+                started_at: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default(),
+                redactions: Vec::new(),
+                max_message_len: None,
+                syslog_udp: None,
+                dedup_window: None,
+                lock_timeout: None,
+                drop_on_timeout: false,
+                dropped: AtomicU64::new(0),
+                unreported: AtomicU64::new(0),
+                file_system,
+                created_file: existing.is_none(),
+                had_content,
+                enabled: AtomicBool::new(true),
+                thread_buffers: None,
+                write_lock: Mutex::new(WriteState::default()),
+                last_error: Mutex::new(None),
+            }),
+            configured_late: None,
+        })
+    }
+
+    /// Checks `path` the way `Logger::new` would without creating anything
+    /// or writing to the log. A missing directory or file is only probed
+    /// for, with an empty file that is removed right away. Fails only if
+    /// `path` itself is rejected, e.g. for not ending in `.log`.
+    pub fn diagnose(path: &str) -> Result<Diagnostics, Box<dyn Error>> {
+        let path = crate::create_path_from_str(path)?;
+        let dir = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let mut diagnostics = Diagnostics { dir_exists: dir.is_dir(), ..Diagnostics::default() };
+        if diagnostics.dir_exists {
+            diagnostics.file_exists = path.is_file();
+            if diagnostics.file_exists {
+                diagnostics.file_size = Some(fs::metadata(&path)?.len());
+                diagnostics.writable = fs::OpenOptions::new().append(true).open(&path).is_ok();
+            } else {
+                diagnostics.writable = !path.exists() && can_create_in(&dir);
+            }
+        } else {
+            let above = dir.parent().filter(|above| !above.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            diagnostics.dir_created_would_be = above.is_dir() && !dir.exists();
+            diagnostics.writable = diagnostics.dir_created_would_be && can_create_in(above);
+        }
+
+        Ok(diagnostics)
+    }
+
+    /// A logger that writes only to stdout, with no file at all.
+    pub fn stdout() -> Logger {
+        Logger::to_stdout_sink(StdoutSink::new())
+    }
+
+    /// A logger that writes only to `sink`, see `Logger::stdout`.
+    pub fn to_stdout_sink(sink: StdoutSink) -> Logger {
+        Logger::without_file(WriteState { stdout: Some(sink), ..WriteState::default() })
+    }
+
+    /// A logger that only keeps its last entries in `sink`, for writing
+    /// them out later with `RingSink::dump_to`. Keep a clone of `sink` to
+    /// dump from.
+    pub fn to_ring_sink(sink: RingSink) -> Logger {
+        Logger::without_file(WriteState { ring: Some(sink), ..WriteState::default() })
+    }
+
+    /// A logger that only keeps its entries in `sink`, for tests of code
+    /// that takes a `Logger`. Keep a clone of `sink` to check them.
+    pub fn to_memory_sink(sink: MemorySink) -> Logger {
+        Logger::to_sinks(vec![Box::new(sink)])
+    }
+
+    /// A logger that writes to `file`, an already open handle such as one
+    /// passed down by a parent process, in the same record layout as a log
+    /// it opens itself. There's no path, so nothing is validated or
+    /// created, and what needs one isn't available: no run separator is
+    /// written, `with_prepend` and `cross_process_lock` fail `build`, and
+    /// `entries` is unsupported. Open `file` for appending to get what
+    /// `O_APPEND` gives writers sharing it.
+    pub fn from_file(file: fs::File) -> Logger {
+        Logger::without_file(WriteState { handle: Some(file), ..WriteState::default() })
+    }
+
+    /// A logger with no file that writes every entry to each of `sinks`,
+    /// in order. See `also_sink` on what happens when one of them fails.
+    pub fn to_sinks(sinks: Vec<Box<dyn Sink>>) -> Logger {
+        Logger::without_file(WriteState { sinks, ..WriteState::default() })
+    }
+
+    /// A logger with no file, writing to whichever sink is set in `state`.
+    fn without_file(state: WriteState) -> Logger {
+        Logger {
+            inner: Arc::new(Inner {
+                path: None,
+                terminator: b"\n".to_vec(),
+                checksums: None,
+                cross_process_lock: false,
+                prepend: false,
+                millis_on_collision: false,
+                error_debug: false,
+                backtraces: BacktraceMode::Off,
+                heartbeat: None,
+                started: Instant::now(),
+                started_at: SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default(),
+                redactions: Vec::new(),
+                max_message_len: None,
+                syslog_udp: None,
+                dedup_window: None,
+                lock_timeout: None,
+                drop_on_timeout: false,
+                dropped: AtomicU64::new(0),
+                unreported: AtomicU64::new(0),
+                file_system: Arc::new(RealFileSystem),
+                created_file: false,
+                had_content: false,
+                enabled: AtomicBool::new(true),
+                thread_buffers: None,
+                write_lock: Mutex::new(state),
+                last_error: Mutex::new(None),
+            }),
+            configured_late: None,
+        }
+    }
+
+    /// Ends each record with `terminator` instead of a newline, e.g. `b"\0"`
+    /// for messages that may themselves contain newlines. Read such a log
+    /// back with `Logger::entries` or `entries_with_terminator`.
+    ///
+    /// # Panics
+    /// If `terminator` is empty.
+    pub fn with_terminator(mut self, terminator: &[u8]) -> Logger {
+        assert!(!terminator.is_empty(), "terminator must not be empty");
+        if let Some(inner) = self.configure("with_terminator") {
+            inner.terminator = terminator.to_vec();
+        }
+        self
+    }
+
+    /// Marks where this run of the program starts when the log already had
+    /// content from an earlier one, with a line like
+    /// `===== run started 2024-06-01 14:05:03 (format 1) =====`, giving the
+    /// time this logger was created (UTC) and `FORMAT_VERSION`. The line is
+    /// only written right before this logger's first entry, so a run that
+    /// logs nothing leaves the file alone. Readers skip these lines.
+    pub fn with_run_separator(mut self, enabled: bool) -> Logger {
+        if let Some(inner) = self.configure("with_run_separator") {
+            inner.write_lock.get_mut().unwrap_or_else(|e| e.into_inner()).separator_pending = enabled && inner.had_content;
+        }
+        self
+    }
+
+    /// Writes a snapshot of where the program runs right before this
+    /// logger's first entry, and after its run separator if there is one:
+    /// `version`, `std::env::consts::OS` and `ARCH`, the working directory
+    /// and each of `env_vars`, one per line (`\t` being a tab):
+    ///
+    /// ```text
+    /// 1717250703 - environment
+    /// \t| version: 2.1.0
+    /// \t| os: linux
+    /// \t| arch: x86_64
+    /// \t| cwd: /srv/app
+    /// \t| env RUST_LOG: <unset>
+    /// ```
+    ///
+    /// Only the variables named are read, so keep secrets off the list.
+    /// Variables that aren't set show as `<unset>`, and line breaks in
+    /// values are written as `\n`.
+    pub fn with_environment_snapshot(mut self, version: &str, env_vars: &[&str]) -> Logger {
+        if let Some(inner) = self.configure("with_environment_snapshot") {
+            let state = inner.write_lock.get_mut().unwrap_or_else(|e| e.into_inner());
+            state.snapshot = Some((version.to_string(), env_vars.iter().map(|var| var.to_string()).collect()));
+        }
+        self
+    }
+
+    /// Ends every entry with ` #` and an 8 hex digit FNV-1a checksum of the
+    /// line, which `verify` or `verify_chained` can check later. Read the
+    /// log back with `Logger::entries` or `Entries::with_checksums` to get
+    /// the checksums out of the messages.
+    pub fn with_checksums(mut self, checksums: Checksums) -> Logger {
+        if let Some(inner) = self.configure("with_checksums") {
+            inner.checksums = Some(checksums);
+        }
+        self
+    }
+
+    /// Takes a `FileLock` on the log around every write, so separate
+    /// programs sharing the file don't interleave run separators or break
+    /// each other's checksum chains. Has no effect on a stdout logger.
+    pub fn cross_process_lock(mut self, enabled: bool) -> Logger {
+        if let Some(inner) = self.configure("cross_process_lock") {
+            inner.cross_process_lock = enabled;
+        }
+        self
+    }
+
+    /// Gives this process a log of its own by putting its pid in front of
+    /// the extension, e.g. `errors.log` becomes `errors.48213.log`. The
+    /// file is only made on the first write, and the one `Logger::new`
+    /// made under the shared name is removed again if it's still empty.
+    /// `Logger::path` returns the new name. Call it before
+    /// `with_run_separator`, which looks at the file's content.
+    pub fn per_process(mut self, enabled: bool) -> Logger {
+        let Some(inner) = self.configure("per_process") else {
+            return self;
+        };
+        let Some(path) = inner.path.as_ref().filter(|_| enabled).cloned() else {
+            return self;
+        };
+
+        if inner.created_file && inner.file_system.metadata(&path).is_ok_and(|meta| meta.len == 0) {
+            let _ = inner.file_system.remove(&path);
+        }
+        let own = per_process_path(&path, std::process::id());
+        inner.created_file = false;
+        inner.had_content = inner.file_system.metadata(&own).is_ok_and(|meta| meta.len > 0);
+        inner.write_lock.get_mut().unwrap_or_else(|e| e.into_inner()).separator_pending &= inner.had_content;
+        inner.path = Some(own);
+        self
+    }
+
+    /// Skips a message when the identical text was written less than
+    /// `window` ago, even with other messages in between. Timestamps have
+    /// whole-second precision, so the window is too, and one shorter than a
+    /// second fails `build`. Up to 64 distinct
+    /// recent messages are remembered and a skipped message doesn't extend
+    /// the window.
+    pub fn with_dedup_window(mut self, window: Duration) -> Logger {
+        if let Some(inner) = self.configure("with_dedup_window") {
+            inner.dedup_window = Some(window);
+        }
+        self
+    }
+
+    /// Writes the milliseconds too, as `<seconds>.<millis> - <error>`, but
+    /// only for an entry in the same second as the one written before it,
+    /// so entries that would otherwise look simultaneous can be told apart.
+    pub fn with_millis_on_collision(mut self, enabled: bool) -> Logger {
+        if let Some(inner) = self.configure("with_millis_on_collision") {
+            inner.millis_on_collision = enabled;
+        }
+        self
+    }
+
+    /// Has `log_error` write the error's `Debug` form after its `Display`
+    /// text, as `<display> | debug: <debug>`, for errors whose message
+    /// leaves out the details. Debug output can be long and span lines, so
+    /// it's worth pairing with `with_max_message_len`.
+    pub fn with_error_debug(mut self, enabled: bool) -> Logger {
+        if let Some(inner) = self.configure("with_error_debug") {
+            inner.error_debug = enabled;
+        }
+        self
+    }
+
+    /// Adds a backtrace of where the entry was logged from to the entries
+    /// `mode` picks, as continuation lines after `backtrace:`. It's captured
+    /// whatever `RUST_BACKTRACE` says, but how much it shows depends on the
+    /// platform and the build's debug info; where it can't be captured at
+    /// all the entry ends in `backtrace: unsupported` instead. Capturing is
+    /// slow, so this is off by default.
+    pub fn capture_backtrace(mut self, mode: BacktraceMode) -> Logger {
+        if let Some(inner) = self.configure("capture_backtrace") {
+            inner.backtraces = mode;
+        }
+        self
+    }
+
+    /// Writes a line like
+    /// `===== heartbeat 2024-06-01 14:05:03 entries=1000 uptime=3600s =====`
+    /// after the entries `heartbeat` picks, to show the logger is still
+    /// alive and give long logs points to split at. `entries` counts what
+    /// this logger has logged and `uptime` is in whole seconds since it was
+    /// created. Like a run separator it isn't an entry: readers skip it, so
+    /// it's left out of `crate::stats`, `crate::info` and exports, and it
+    /// isn't passed on to sinks or syslog.
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Logger {
+        if let Some(inner) = self.configure("with_heartbeat") {
+            inner.heartbeat = Some(heartbeat);
+        }
+        self
+    }
+
+    /// Replaces every match of `patterns` in messages and field values,
+    /// though not field names, with `[REDACTED]` before anything is
+    /// written anywhere, including the file, sinks and anything echoed to
+    /// stderr. A pattern matches itself, or with a trailing `*` itself
+    /// followed by everything up to the next whitespace, so `password=*`
+    /// takes out `password=hunter2` whole.
+    ///
+    /// # Panics
+    /// If a pattern is empty or only `*`.
+    pub fn with_redaction(mut self, patterns: &[&str]) -> Logger {
+        assert!(patterns.iter().all(|pattern| !matches!(*pattern, "" | "*")), "redaction patterns must not be empty");
+        let patterns = patterns.iter().map(|pattern| pattern.to_string()).collect();
+        if let Some(inner) = self.configure("with_redaction") {
+            inner.redactions.push(Redaction::Patterns(patterns));
+        }
+        self
+    }
+
+    /// `with_redaction` with `redactor` deciding what to take out: it gets
+    /// each message and field value and returns what's written instead.
+    /// Redactors run in the order they were added, after or before
+    /// `with_redaction` patterns depending on which came first. One must
+    /// not log through this logger.
+    pub fn with_redactor(mut self, redactor: Box<dyn Fn(&str) -> String + Send + Sync>) -> Logger {
+        if let Some(inner) = self.configure("with_redactor") {
+            inner.redactions.push(Redaction::Custom(redactor));
+        }
+        self
+    }
+
+    /// Splits a message longer than `max` bytes into entries of at most
+    /// `max` bytes each, cut at character boundaries and numbered like
+    /// `(1/3) `, `(2/3) `, `(3/3) ` in front of their text. The parts of one
+    /// message are written together, each as an entry of its own with its
+    /// own timestamp, so no single line grows without bound.
+    ///
+    /// # Panics
+    /// If `max` is 0.
+    pub fn with_max_message_len(mut self, max: usize) -> Logger {
+        assert!(max > 0, "max message length must not be 0");
+        if let Some(inner) = self.configure("with_max_message_len") {
+            inner.max_message_len = Some(max);
+        }
+        self
+    }
+
+    /// Also sends every entry written to `sink`'s syslog collector, after
+    /// it's in the file or on stdout. Sending never fails or delays a
+    /// write; `failed_syslog_sends` counts what got lost.
+    pub fn also_syslog_udp(mut self, sink: SyslogUdpSink) -> Logger {
+        if let Some(inner) = self.configure("also_syslog_udp") {
+            inner.syslog_udp = Some(sink);
+        }
+        self
+    }
+
+    /// Also writes every entry to `sink`, after the logger's own output and
+    /// after any sinks added before it. A sink failing doesn't stop the
+    /// entry from reaching the file or the remaining sinks; the write then
+    /// returns a `SinkError` naming every sink that failed, by the order
+    /// they were added in, counting from 1.
+    pub fn also_sink(mut self, sink: Box<dyn Sink>) -> Logger {
+        if let Some(inner) = self.configure("also_sink") {
+            inner.write_lock.get_mut().unwrap_or_else(|e| e.into_inner()).sinks.push(sink);
+        }
+        self
+    }
+
+    /// Datagrams `also_syslog_udp` couldn't send, 0 without a syslog sink.
+    pub fn failed_syslog_sends(&self) -> u64 {
+        self.inner.syslog_udp.as_ref().map_or(0, SyslogUdpSink::failed_sends)
+    }
+
+    /// Gives up on a write that can't take its locks within `timeout`: the
+    /// logger's own lock, which a stuck write or flush in another thread may
+    /// hold, and with `cross_process_lock` the lock file as well. Both share
+    /// one budget. The write then fails with `TimeoutError`, or with
+    /// `drop_on_lock_timeout` the entry is counted and dropped.
+    pub fn lock_timeout(mut self, timeout: Duration) -> Logger {
+        if let Some(inner) = self.configure("lock_timeout") {
+            inner.lock_timeout = Some(timeout);
+        }
+        self
+    }
+
+    /// Drops entries whose write runs into `lock_timeout` instead of
+    /// failing, so logging stays best effort. They're counted like the ones
+    /// `try_log` drops.
+    pub fn drop_on_lock_timeout(mut self, enabled: bool) -> Logger {
+        if let Some(inner) = self.configure("drop_on_lock_timeout") {
+            inner.drop_on_timeout = enabled;
+        }
+        self
+    }
+
+    /// How many entries this logger and its clones have dropped, through
+    /// `try_log` or `drop_on_lock_timeout`.
+    pub fn dropped_count(&self) -> u64 {
+        self.inner.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Writes each new entry at the top of the file instead of the bottom,
+    /// for dashboards that only show the head. Every write reads the whole
+    /// log and rewrites it through a temporary file and a rename, so it
+    /// costs time proportional to the size of the log; keep such logs
+    /// small. Followers and `entries_from_offset` assume appends and won't
+    /// see prepended entries, and `Checksums::Chained` chains in writing
+    /// order, which is then bottom to top.
+    pub fn with_prepend(mut self, enabled: bool) -> Logger {
+        if let Some(inner) = self.configure("with_prepend") {
+            inner.prepend = enabled;
+        }
+        self
+    }
+
+    /// Collects each thread's entries in a buffer of its own instead of
+    /// writing them right away, so logging threads don't wait on each other.
+    /// `flush_all` writes the buffered entries ordered by the time they were
+    /// logged.
+    ///
+    /// Buffered entries aren't in the file, nor reported by `last_error`,
+    /// until a flush. Dropping the last clone of the logger flushes, and a
+    /// thread's buffer outlives the thread, so entries from threads that have
+    /// exited are flushed too.
+    pub fn with_thread_buffers(mut self) -> Logger {
+        let id = NEXT_LOGGER_ID.fetch_add(1, Ordering::Relaxed);
+        if let Some(inner) = self.configure("with_thread_buffers") {
+            inner.thread_buffers = Some((id, Mutex::new(Vec::new())));
+        }
+        self
+    }
+
+    /// Ends a chain of options by checking that they fit together, so a
+    /// combination that would quietly do nothing fails here instead of
+    /// misbehaving once the logger is in use. Fails with `ConfigError` for
+    /// `drop_on_lock_timeout` without a `lock_timeout`, a `with_heartbeat`
+    /// of no entries or no time, a `with_dedup_window` under a second,
+    /// `with_prepend` or `cross_process_lock` on a logger that writes to a
+    /// sink rather than a file, and for any option set on a logger after it
+    /// was cloned, which its clones share and so can't change.
+    pub fn build(self) -> Result<Logger, Box<dyn Error>> {
+        let inner = &self.inner;
+        let conflict = |message: &str| Err(Box::new(ConfigError { message: message.to_owned() }) as Box<dyn Error>);
+
+        if let Some(option) = self.configured_late {
+            return conflict(&format!("{} was called after the logger was cloned, so it was not applied", option));
+        }
+
+        if inner.drop_on_timeout && inner.lock_timeout.is_none() {
+            return conflict("drop_on_lock_timeout needs a lock_timeout to give up after");
+        }
+        if matches!(inner.heartbeat, Some(Heartbeat::EveryEntries(0) | Heartbeat::EveryDuration(Duration::ZERO))) {
+            return conflict("with_heartbeat needs a nonzero interval");
+        }
+        if inner.dedup_window.is_some_and(|window| window.as_secs() == 0) {
+            return conflict("with_dedup_window needs a window of at least a second");
+        }
+        if inner.path.is_none() {
+            if inner.prepend {
+                return conflict("with_prepend needs a log file, but this logger writes to a sink");
+            }
+            if inner.cross_process_lock {
+                return conflict("cross_process_lock needs a log file, but this logger writes to a sink");
+            }
+        }
+        Ok(self)
+    }
+
+    /// Writes out what every thread has buffered, see `with_thread_buffers`,
+    /// then flushes every sink added with `also_sink` or `to_sinks`.
+    pub fn flush_all(&self) -> Result<(), Box<dyn Error>> {
+        let result = self.inner.flush_buffers().and_then(|()| self.inner.flush_sinks());
+        self.record(&result);
+        result
+    }
+
+    /// The builder methods only run before a logger is shared, so clones
+    /// never see their settings change under them. On a clone there's
+    /// nothing to change: `option` is left unset and `build` reports it.
+    fn configure(&mut self, option: &'static str) -> Option<&mut Inner> {
+        match Arc::get_mut(&mut self.inner) {
+            Some(inner) => Some(inner),
+            None => {
+                self.configured_late.get_or_insert(option);
+                None
+            }
+        }
+    }
+
+    /// Turns logging on or off for this logger and all its clones. While
+    /// off, `log` writes nothing and returns `Ok`.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.inner.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.inner.enabled.load(Ordering::Relaxed)
+    }
+
+    /// The file this logger writes to, `None` when it writes to a sink or
+    /// to a handle given to `from_file`.
+    pub fn path(&self) -> Option<&Path> {
+        self.inner.path.as_deref()
+    }
+
+    /// Appends `error` to the log. The outcome is also remembered for
+    /// `last_error`, so callers that ignore the result can check later.
+    pub fn log(&self, error: String) -> Result<(), Box<dyn Error>> {
+        self.log_as(OutputFormat::Text, error)
+    }
+
+    /// `log` with this one entry written in `format`, e.g. a structured
+    /// event as JSON in an otherwise plain log. Mixing formats in one file
+    /// makes it harder to read back: `entries` reports the JSON lines as
+    /// `Record::Malformed`, and `entries_json` the plain ones, so a reader
+    /// has to try both on every line.
+    pub fn log_as(&self, format: OutputFormat, error: String) -> Result<(), Box<dyn Error>> {
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        self.log_time(now, format, error)
+    }
+
+    /// Logs `error` with `fields` attached: in `OutputFormat::Text` as
+    /// ` key=value` after the message, values quoted like logfmt when
+    /// they hold spaces, quotes or line breaks, and in `OutputFormat::Json`
+    /// as extra top-level string keys, see `JSON_SCHEMA_VERSION`, sorted by
+    /// key with the last of any repeated key kept. Nothing is written if a key is empty, contains
+    /// whitespace, `=`, `"` or control characters, or is one of the keys a
+    /// JSON entry uses itself: `v`, `timestamp`, `code` or `message`.
+    pub fn log_fields(&self, format: OutputFormat, error: String, fields: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+        for (key, _) in fields {
+            check_field_name(key)?;
+        }
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        self.log_time_with(now, format, error, fields.to_vec())
+    }
+
+    /// `log` with the file and line this is called from attached as a
+    /// `location=<file>:<line>` field, as `log_fields` writes it. The
+    /// location comes from `#[track_caller]`, so a helper that wraps this
+    /// reports its own line unless it's `#[track_caller]` as well, and the
+    /// same goes for whatever wraps that helper: the attribute has to be on
+    /// every function between the call to report and this one.
+    #[track_caller]
+    pub fn log_here(&self, error: String) -> Result<(), Box<dyn Error>> {
+        let caller = Location::caller();
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        self.log_time_with(now, OutputFormat::Text, error, vec![location_field(caller)])
+    }
+
+    /// Logs `error`'s `Display` text, followed by its `Debug` form when
+    /// the logger was set up `with_error_debug`.
+    pub fn log_error(&self, error: &dyn Error) -> Result<(), Box<dyn Error>> {
+        self.log(self.describe_error(error))
+    }
+
+    /// The message `log_error` writes for `error`.
+    fn describe_error(&self, error: &dyn Error) -> String {
+        let mut message = match self.inner.error_debug {
+            true => format!("{} | debug: {:?}", error, error),
+            false => error.to_string(),
+        };
+        if self.inner.backtraces == BacktraceMode::OnError {
+            let backtrace = Backtrace::force_capture();
+            match backtrace.status() {
+                BacktraceStatus::Captured => message.push_str(&format!("\nbacktrace:\n{}", backtrace.to_string().trim_end())),
+                BacktraceStatus::Disabled => message.push_str("\nbacktrace: disabled"),
+                _ => message.push_str("\nbacktrace: unsupported"),
+            }
+        }
+        message
+    }
+
+    /// The line `log` would write for `error` at `time`, without writing
+    /// it: the same bytes the file gets between the terminators, with this
+    /// logger's milliseconds and checksum settings applied as they would be
+    /// to its next entry. A chained checksum may read the file's last line
+    /// to chain from. A message over `with_max_message_len` is rendered as
+    /// one line even though `log` would split it, and a pending separator
+    /// or environment snapshot isn't included.
+    pub fn render(&self, time: SystemTime, error: &str) -> Result<String, Box<dyn Error>> {
+        let time = time.duration_since(SystemTime::UNIX_EPOCH)?;
+        let (error, _) = self.inner.redact(error, &[]);
+        let state = self.inner.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        Ok(self.inner.line_for(&state, time, OutputFormat::Text, &error)?.0)
+    }
+
+    /// A handle that logs through this logger with every entry starting
+    /// `req=<id> `, so all the entries for one request or job can be
+    /// found together. It borrows the logger, so making one per request
+    /// is cheap.
+    pub fn with_scope(&self, id: &str) -> Scope<'_> {
+        Scope { logger: self, prefix: format!("req={} ", id) }
+    }
+
+    /// Logs `error` only if that can be done without waiting on another
+    /// thread or process, otherwise drops it and returns `false`. Failed
+    /// writes are dropped too, and set `last_error` as usual. Each drop is
+    /// counted in `dropped_count`, and the next write by `log` or a flush
+    /// puts an entry like `3 entries dropped` before its own.
+    ///
+    /// A logger with thread buffers only ever appends to this thread's
+    /// buffer, so this is the same as `log` there.
+    pub fn try_log(&self, error: String) -> bool {
+        if !self.is_enabled() {
+            return true;
+        }
+        let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        if self.inner.thread_buffers.is_some() {
+            return self.log_time(now, OutputFormat::Text, error).is_ok();
+        }
+
+        match self.inner.write_with(now, OutputFormat::Text, &error, &[], true) {
+            Ok(()) => {
+                self.record(&Ok(()));
+                true
+            }
+            Err(e) => {
+                if !e.is::<TimeoutError>() {
+                    self.record(&Err(e));
+                }
+                self.inner.count_drop();
+                false
+            }
+        }
+    }
+
+    /// Pretends this logger was created at `time`, for the run separator.
+    #[cfg(test)]
+    fn started_at(mut self, time: Duration) -> Logger {
+        if let Some(inner) = self.configure("started_at") {
+            inner.started_at = time;
+        }
+        self
+    }
+
+    #[cfg(test)]
+    fn log_at(&self, timestamp: u64, error: String) -> Result<(), Box<dyn Error>> {
+        self.log_time(Duration::from_secs(timestamp), OutputFormat::Text, error)
+    }
+
+    /// `log_as` with `time` since `UNIX_EPOCH` as the moment of logging.
+    fn log_time(&self, time: Duration, format: OutputFormat, error: String) -> Result<(), Box<dyn Error>> {
+        self.log_time_with(time, format, error, Vec::new())
+    }
+
+    /// `log_time` with `fields` added to the entry.
+    fn log_time_with(&self, time: Duration, format: OutputFormat, error: String, fields: Fields) -> Result<(), Box<dyn Error>> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        if let Some((id, buffers)) = &self.inner.thread_buffers {
+            let buffer = THREAD_BUFFERS.with(|own| {
+                let mut own = own.borrow_mut();
+                if let Some((_, buffer)) = own.iter().find(|(logger, _)| logger == id) {
+                    return Arc::clone(buffer);
+                }
+                // buffers of loggers that are gone are only held here
+                own.retain(|(_, buffer)| Arc::strong_count(buffer) > 1);
+                let buffer = ThreadBuffer::default();
+                buffers.lock().unwrap_or_else(|e| e.into_inner()).push(Arc::clone(&buffer));
+                own.push((*id, Arc::clone(&buffer)));
+                buffer
+            });
+            buffer.lock().unwrap_or_else(|e| e.into_inner()).push((time, format, error, fields));
+            return Ok(());
+        }
+
+        let result = self.inner.write(time, format, &error, &fields);
+        self.record(&result);
+        result
+    }
+
+    fn record(&self, result: &Result<(), Box<dyn Error>>) {
+        let mut last_error = self.inner.last_error.lock().unwrap_or_else(|e| e.into_inner());
+        *last_error = result.as_ref().err().map(|e| e.to_string());
+    }
+
+    /// The message of the most recent failed write, or `None` if the last
+    /// write succeeded or nothing has been written yet.
+    pub fn last_error(&self) -> Option<String> {
+        self.inner.last_error.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Streams this logger's file back, split on its terminator, with
+    /// checksums read as such when it writes them.
+    pub fn entries(&self) -> Result<Entries, Box<dyn Error>> {
+        let Some(path) = &self.inner.path else {
+            return Err(Box::new(io::Error::new(io::ErrorKind::Unsupported, "logger has no file to read back")));
+        };
+        let entries = entries_with_terminator(&path.to_string_lossy(), &self.inner.terminator)?;
+        Ok(if self.inner.checksums.is_some() { entries.with_checksums() } else { entries })
+    }
+
+}
+
+/// Entries tagged with a correlation id, see `Logger::with_scope`.
+#[derive(Debug, Clone)]
+pub struct Scope<'a> {
+    logger: &'a Logger,
+    prefix: String,
+}
+
+impl Scope<'_> {
+    /// `Logger::log` with the id in front of `error`.
+    pub fn log(&self, error: String) -> Result<(), Box<dyn Error>> {
+        self.logger.log(format!("{}{}", self.prefix, error))
+    }
+
+    /// `Logger::log_error` with the id in front.
+    pub fn log_error(&self, error: &dyn Error) -> Result<(), Box<dyn Error>> {
+        self.log(self.logger.describe_error(error))
+    }
+
+    /// `Logger::try_log` with the id in front of `error`.
+    pub fn try_log(&self, error: String) -> bool {
+        self.logger.try_log(format!("{}{}", self.prefix, error))
+    }
+}
+
+impl Inner {
+    /// Drains every thread's buffer and writes the entries in timestamp
+    /// order. Holding the list of buffers throughout keeps two flushes from
+    /// interleaving. If a write fails, the entries not yet written go back
+    /// into a buffer for the next flush.
+    fn flush_buffers(&self) -> Result<(), Box<dyn Error>> {
+        let Some((_, buffers)) = &self.thread_buffers else {
+            return Ok(());
+        };
+        let mut buffers = buffers.lock().unwrap_or_else(|e| e.into_inner());
+
+        let mut pending = Vec::new();
+        for buffer in buffers.iter() {
+            pending.append(&mut buffer.lock().unwrap_or_else(|e| e.into_inner()));
+        }
+        // a buffer only this list holds belongs to a thread that has exited
+        buffers.retain(|buffer| Arc::strong_count(buffer) > 1);
+        pending.sort_by_key(|(time, _, _, _)| *time);
+
+        let mut pending = pending.into_iter();
+        while let Some((time, format, error, fields)) = pending.next() {
+            if let Err(e) = self.write(time, format, &error, &fields) {
+                let unwritten = std::iter::once((time, format, error, fields)).chain(pending);
+                let keeper = buffers.first().cloned().unwrap_or_default();
+                keeper.lock().unwrap_or_else(|e| e.into_inner()).extend(unwritten);
+                if buffers.is_empty() {
+                    buffers.push(keeper);
+                }
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Every step that looks at the file and then changes it happens under
+    /// `write_lock`, so threads sharing a logger can't undo each other's
+    /// appends.
+    fn write(&self, time: Duration, format: OutputFormat, error: &str, fields: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+        self.write_with(time, format, error, fields, false)
+    }
+
+    /// `write`, or with `nonblocking` a write that fails with
+    /// `TimeoutError` at once if a lock is taken. Blocking writes first
+    /// report entries dropped since the last one that did.
+    fn write_with(&self, time: Duration, format: OutputFormat, error: &str, fields: &[(String, String)], nonblocking: bool) -> Result<(), Box<dyn Error>> {
+        let (error, fields) = self.redact(error, fields);
+        let (error, fields) = (error.as_ref(), fields.as_ref());
+        let Some(_writing) = enter_write(error) else {
+            return Ok(());
+        };
+        let deadline = match nonblocking {
+            true => Some(Instant::now()),
+            false => self.lock_timeout.map(|timeout| Instant::now() + timeout),
+        };
+        let Some(mut state) = self.lock_state(deadline) else {
+            return self.timed_out("the logger's write lock", nonblocking);
+        };
+        let dedup_hash = self.dedup_window.map(|_| {
+            let mut hasher = DefaultHasher::new();
+            error.hash(&mut hasher);
+            hasher.finish()
+        });
+        if let (Some(window), Some(hash)) = (self.dedup_window, dedup_hash) {
+            if state.seen(time.as_secs(), hash, window.as_secs()) {
+                return Ok(());
+            }
+        }
+        let _file_lock = match &self.path {
+            Some(path) if self.cross_process_lock && state.stdout.is_none() => {
+                make_directory(&*self.file_system, path).map_err(IoContext::wrap("create the directory of", path))?;
+                let timeout = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+                match FileLock::acquire_with(path, LockOptions { timeout, ..LockOptions::default() }) {
+                    Ok(lock) => Some(lock),
+                    Err(e) if e.kind() == io::ErrorKind::TimedOut => return self.timed_out("the lock file", nonblocking),
+                    Err(e) => return Err(Box::new(e)),
+                }
+            }
+            _ => None,
+        };
+
+        let dropped = if nonblocking { 0 } else { self.unreported.swap(0, Ordering::Relaxed) };
+        if dropped > 0 {
+            let summary = format!("{} {} dropped", dropped, if dropped == 1 { "entry" } else { "entries" });
+            if let Err(e) = self.emit_own(&mut state, time, &summary) {
+                self.unreported.fetch_add(dropped, Ordering::Relaxed);
+                return Err(e);
+            }
+        }
+        if let Some((version, env_vars)) = &state.snapshot {
+            let snapshot = environment_snapshot(version, env_vars);
+            self.emit_own(&mut state, time, &snapshot)?;
+            state.snapshot = None;
+        }
+        match self.max_message_len {
+            Some(max) if error.len() > max => {
+                let parts = split_message(error, max);
+                for (i, part) in parts.iter().enumerate() {
+                    self.emit(&mut state, time, format, &format!("({}/{}) {}", i + 1, parts.len(), part), fields)?;
+                }
+            }
+            _ => self.emit(&mut state, time, format, error, fields)?,
+        }
+        if let Some(hash) = dedup_hash {
+            state.remember(hash, time.as_secs());
+        }
+        self.heartbeat(&mut state, time)
+    }
+
+    /// `error` and the values of `fields` after every `Redaction`.
+    fn redact<'a>(&self, error: &'a str, fields: &'a [(String, String)]) -> (Cow<'a, str>, Cow<'a, [(String, String)]>) {
+        if self.redactions.is_empty() {
+            return (Cow::Borrowed(error), Cow::Borrowed(fields));
+        }
+        let redact = |text: &str| self.redactions.iter().fold(text.to_owned(), |text, redaction| redaction.apply(&text));
+        let fields = fields.iter().map(|(key, value)| (key.clone(), redact(value))).collect();
+        (Cow::Owned(redact(error)), Cow::Owned(fields))
+    }
+
+    /// Counts the entry just logged at `time` and writes a heartbeat after
+    /// it when `Logger::with_heartbeat` says one is due.
+    fn heartbeat(&self, state: &mut WriteState, time: Duration) -> Result<(), Box<dyn Error>> {
+        let Some(heartbeat) = self.heartbeat else {
+            return Ok(());
+        };
+        state.logged += 1;
+        let last = *state.last_heartbeat.get_or_insert(time);
+        let due = match heartbeat {
+            Heartbeat::EveryEntries(n) => n > 0 && state.logged.is_multiple_of(n),
+            Heartbeat::EveryDuration(interval) => !interval.is_zero() && time.saturating_sub(last) >= interval,
+        };
+        if !due {
+            return Ok(());
+        }
+
+        let line = format!(
+            "===== heartbeat {} entries={} uptime={}s =====",
+            format_datetime(time.as_secs()),
+            state.logged,
+            self.started.elapsed().as_secs()
+        );
+        let (line, _) = self.redact(&line, &[]);
+        self.write_out(state, line)?;
+        state.last_heartbeat = Some(time);
+        Ok(())
+    }
+
+    /// Emits a plain entry the logger writes itself, such as the dropped
+    /// count or the environment snapshot, redacted like the entries it's given:
+    /// those are redacted on the way in, these would otherwise never be.
+    fn emit_own(&self, state: &mut WriteState, time: Duration, text: &str) -> Result<(), Box<dyn Error>> {
+        let (text, _) = self.redact(text, &[]);
+        self.emit(state, time, OutputFormat::Text, &text, &[])
+    }
+
+    /// The plain text line `emit` would write for `error` at `time` given
+    /// `state`, and the checksum the next line chains to when the logger
+    /// has `Checksums::Chained`.
+    fn line_for(&self, state: &WriteState, time: Duration, format: OutputFormat, error: &str) -> Result<(String, Option<u32>), Box<dyn Error>> {
+        let timestamp = time.as_secs();
+        let mut line = if self.millis_on_collision && state.last_second == Some(timestamp) {
+            crate::format_at(format!("{}.{:03}", timestamp, time.subsec_millis()), error, &self.terminator)
+        } else {
+            crate::format_at(timestamp, error, &self.terminator)
+        };
+
+        let Some(checksums) = self.checksums.filter(|_| format == OutputFormat::Text) else {
+            return Ok((line, None));
+        };
+        let previous = match checksums {
+            Checksums::PerLine => None,
+            Checksums::Chained => match state.chain {
+                Some(previous) => previous,
+                None => match &self.path {
+                    Some(path) if path.is_file() => last_checksum(&path.to_string_lossy())?,
+                    _ => None,
+                },
+            },
+        };
+        let sum = checksum(previous, line.as_bytes());
+        line = format!("{} #{:08x}", line, sum);
+        Ok((line, Some(sum).filter(|_| checksums == Checksums::Chained)))
+    }
+
+    /// Formats and writes one entry, with `write_lock` and any `FileLock`
+    /// already held. Sinks always get the entry as it would be in plain
+    /// text, whatever `format` the file gets.
+    fn emit(&self, state: &mut WriteState, time: Duration, format: OutputFormat, error: &str, fields: &[(String, String)]) -> Result<(), Box<dyn Error>> {
+        let error = match format {
+            OutputFormat::Text if !fields.is_empty() => Cow::Owned(with_fields(error, fields)),
+            _ => Cow::Borrowed(error),
+        };
+        let error = error.as_ref();
+        let (line, chained) = self.line_for(state, time, format, error)?;
+        let written = match format {
+            OutputFormat::Text => Cow::Borrowed(line.as_str()),
+            OutputFormat::Json => {
+                let mut entry = entry_of(&line, false, time, error);
+                entry.fields = fields.iter().cloned().collect();
+                Cow::Owned(entry.to_json())
+            }
+        };
+
+        // only what was written counts, or the next entry would chain to a
+        // checksum the log never got
+        self.write_out(state, written)?;
+        state.last_second = Some(time.as_secs());
+        if chained.is_some() {
+            state.chain = Some(chained);
+        }
+        self.copy_out(state, &line, time, error)
+    }
+
+    /// Writes the record `written` to wherever this logger writes: its
+    /// stdout or ring sink, its handle, or its file.
+    fn write_out(&self, state: &mut WriteState, written: Cow<'_, str>) -> Result<(), Box<dyn Error>> {
+        if let Some(sink) = &mut state.stdout {
+            sink.write_record(&written, &self.terminator)?;
+            return Ok(());
+        }
+        if let Some(sink) = &state.ring {
+            sink.push(written.into_owned());
+            return Ok(());
+        }
+        if let Some(file) = &mut state.handle {
+            // see `crate::append_line` on why each record is one write
+            file.write_all(&crate::record(&written, &self.terminator))?;
+            return Ok(());
+        }
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        make_directory(&*self.file_system, path).map_err(IoContext::wrap("create the directory of", path))?;
+
+        let separator = if state.separator_pending {
+            Some(format!("===== run started {} (format {}) =====", format_datetime(self.started_at.as_secs()), FORMAT_VERSION))
+        } else {
+            None
+        };
+
+        if self.prepend {
+            // newest first: the entry, then the separator that starts its run
+            let mut contents = crate::record(&written, &self.terminator);
+            if let Some(separator) = &separator {
+                contents.extend(crate::record(separator, &self.terminator));
+            }
+            match self.file_system.read(path) {
+                Ok(old) => {
+                    // the old tail becomes the file's, so it gets a terminator if it had none
+                    let unterminated = !old.is_empty() && !old.ends_with(&self.terminator);
+                    contents.extend(old);
+                    if unterminated {
+                        contents.extend_from_slice(&self.terminator);
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => return Err(Box::new(IoContext::wrap("read", path)(e))),
+            }
+            replace_through(&*self.file_system, path, &contents)?;
+        } else {
+            // see `crate::append_line` on why each record is one write
+            let mut file = self.file_system.open_append(path).map_err(IoContext::wrap("open", path))?;
+            if let Some(separator) = &separator {
+                file.write_all(&crate::record(separator, &self.terminator)).map_err(IoContext::wrap("append to", path))?;
+            }
+            file.write_all(&crate::record(&written, &self.terminator)).map_err(IoContext::wrap("append to", path))?;
+        }
+
+        state.separator_pending = false;
+        Ok(())
+    }
+
+    /// Passes the entry just written as `line` on to `syslog_udp` and then
+    /// every one of `state.sinks`. All of them get it even when some fail,
+    /// which are then reported together.
+    fn copy_out(&self, state: &mut WriteState, line: &str, time: Duration, error: &str) -> Result<(), Box<dyn Error>> {
+        if self.syslog_udp.is_none() && state.sinks.is_empty() {
+            return Ok(());
+        }
+        let entry = entry_of(line, self.checksums.is_some(), time, error);
+        if let Some(sink) = &self.syslog_udp {
+            sink.send(&entry);
+        }
+
+        let failed: Vec<String> = state
+            .sinks
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, sink)| sink.write_entry(&entry).err().map(|e| format!("sink {}: {}", i + 1, e)))
+            .collect();
+        sink_result("write to", state.sinks.len(), failed)
+    }
+
+    /// Flushes every one of the sinks, reporting failures like `copy_out`.
+    fn flush_sinks(&self) -> Result<(), Box<dyn Error>> {
+        let mut state = self.write_lock.lock().unwrap_or_else(|e| e.into_inner());
+        let failed: Vec<String> = state
+            .sinks
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, sink)| sink.flush().err().map(|e| format!("sink {}: {}", i + 1, e)))
+            .collect();
+        sink_result("flush", state.sinks.len(), failed)
+    }
+
+    /// Takes `write_lock`, or `None` if it's still held by someone else at
+    /// `deadline`. Std's `Mutex` can't wait with a timeout, so this retries
+    /// with the same doubling backoff as `FileLock`.
+    fn lock_state(&self, deadline: Option<Instant>) -> Option<MutexGuard<'_, WriteState>> {
+        let Some(deadline) = deadline else {
+            return Some(self.write_lock.lock().unwrap_or_else(|e| e.into_inner()));
+        };
+
+        let mut backoff = Duration::from_millis(1);
+        loop {
+            match self.write_lock.try_lock() {
+                Ok(state) => return Some(state),
+                Err(TryLockError::Poisoned(e)) => return Some(e.into_inner()),
+                Err(TryLockError::WouldBlock) => {}
+            }
+            let left = deadline.saturating_duration_since(Instant::now());
+            if left.is_zero() {
+                return None;
+            }
+            thread::sleep(backoff.min(left));
+            backoff = (backoff * 2).min(LockOptions::default().max_backoff);
+        }
+    }
+
+    /// The outcome of a write that ran out of `lock_timeout` waiting for
+    /// `lock`, or found it taken when `nonblocking`.
+    fn timed_out(&self, lock: &str, nonblocking: bool) -> Result<(), Box<dyn Error>> {
+        if nonblocking {
+            return Err(Box::new(TimeoutError { message: format!("{} is taken", lock) }));
+        }
+        if self.drop_on_timeout {
+            self.count_drop();
+            return Ok(());
+        }
+        let timeout = self.lock_timeout.unwrap_or_default();
+        Err(Box::new(TimeoutError { message: format!("timed out after {:?} waiting for {}", timeout, lock) }))
+    }
+
+    fn count_drop(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        self.unreported.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempLogDir;
+    use crate::{MemoryFileSystem, Operation};
+    use std::fmt;
+    use std::fs;
+
+    #[test]
+    fn last_error_tracks_failures() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-last-error/errors.log");
+        let logger = Logger::new(path).unwrap();
+        assert_eq!(None, logger.last_error());
+
+        // a directory where the file should be makes the next write fail
+        fs::remove_file(path).unwrap();
+        fs::create_dir(path).unwrap();
+        if logger.log(String::from("lost")).is_ok() {
+            panic!("Writing to a directory should have failed.");
+        }
+        assert!(logger.last_error().is_some());
+
+        fs::remove_dir(path).unwrap();
+        logger.log(String::from("found")).unwrap();
+        assert_eq!(None, logger.last_error());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn read_only_directory_fails_at_new() {
+        let memory = Arc::new(MemoryFileSystem::new());
+        memory.fail(Operation::OpenAppend, io::ErrorKind::PermissionDenied);
+        match Logger::new_with_file_system("./mock-logs/errors.log", memory.clone()) {
+            Ok(_) => panic!("Creating a log in a read-only directory should have failed."),
+            Err(e) => {
+                assert!(e.is::<NotWritableError>());
+                assert_eq!("log directory is not writable: ./mock-logs", e.to_string());
+            }
+        }
+
+        memory.write(Path::new("./mock-logs/existing.log"), b"").unwrap();
+        match Logger::new_with_file_system("./mock-logs/existing.log", memory) {
+            Ok(_) => panic!("Opening a read-only log should have failed."),
+            Err(e) => {
+                assert!(e.is::<NotWritableError>());
+                assert_eq!("log file is not writable: ./mock-logs/existing.log", e.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn diagnose_fresh_and_existing_logs() {
+        let dir = TempLogDir::new();
+        let fresh = Logger::diagnose(&dir.file("logger-diagnose/errors.log")).unwrap();
+        assert_eq!(
+            Diagnostics { dir_exists: false, dir_created_would_be: true, file_exists: false, file_size: None, writable: true },
+            fresh
+        );
+        assert!(!dir.path().join("logger-diagnose").exists());
+
+        let nowhere = Logger::diagnose(&dir.file("logger-diagnose/deeper/errors.log")).unwrap();
+        assert!(!nowhere.dir_created_would_be && !nowhere.writable);
+
+        let path = &dir.file("logger-diagnose-test.log");
+        fs::write(path, "\n1 - one\n").unwrap();
+        assert_eq!(
+            Diagnostics { dir_exists: true, dir_created_would_be: false, file_exists: true, file_size: Some(9), writable: true },
+            Logger::diagnose(path).unwrap()
+        );
+        assert_eq!("\n1 - one\n", fs::read_to_string(path).unwrap());
+        assert!(Logger::diagnose(&dir.file("errors.txt")).is_err());
+    }
+
+    #[test]
+    fn concurrent_writers_lose_nothing() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-concurrent-test.log");
+        let logger = Logger::new(path).unwrap();
+
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let logger = &logger;
+                scope.spawn(move || {
+                    for i in 0..25 {
+                        logger.log(format!("thread {} entry {}", thread, i)).unwrap();
+                    }
+                });
+            }
+        });
+
+        let mut messages: Vec<String> = crate::read_entries(path).unwrap().into_iter().map(|e| e.message).collect();
+        messages.sort();
+        let mut expected: Vec<String> = (0..8).flat_map(|t| (0..25).map(move |i| format!("thread {} entry {}", t, i))).collect();
+        expected.sort();
+        assert_eq!(expected, messages);
+    }
+
+    #[test]
+    fn clones_share_one_log() {
+        fn shareable<T: Clone + Send + Sync>() {}
+        shareable::<Logger>();
+
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-clone-test.log");
+        let logger = Logger::new(path).unwrap();
+        let workers: Vec<_> = (0..12)
+            .map(|worker| {
+                let logger = logger.clone();
+                std::thread::spawn(move || {
+                    for i in 0..20 {
+                        logger.log(format!("worker {} entry {}", worker, i)).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().unwrap();
+        }
+
+        let clone = logger.clone();
+        clone.set_enabled(false);
+        assert!(!logger.is_enabled());
+        logger.log(String::from("while disabled")).unwrap();
+        logger.set_enabled(true);
+        drop(clone);
+        logger.log(String::from("after the clones")).unwrap();
+
+        let raw = fs::read_to_string(path).unwrap();
+        assert!(raw.lines().all(|line| line.is_empty() || crate::reader::parse_line(line).is_some()));
+        let mut messages: Vec<String> = crate::read_entries(path).unwrap().into_iter().map(|e| e.message).collect();
+        assert_eq!(Some("after the clones"), messages.pop().as_deref());
+        messages.sort();
+        let mut expected: Vec<String> = (0..12).flat_map(|w| (0..20).map(move |i| format!("worker {} entry {}", w, i))).collect();
+        expected.sort();
+        assert_eq!(expected, messages);
+    }
+
+    #[test]
+    fn thread_buffers_flush_in_timestamp_order() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-thread-buffers-test.log");
+        let logger = Logger::new(path).unwrap().with_thread_buffers();
+
+        // thread t logs 100 + t, 104 + t, ... and thread 3 runs first, so
+        // arrival order is far from timestamp order; all have exited by the flush
+        for thread in (0..4u64).rev() {
+            let logger = logger.clone();
+            std::thread::spawn(move || {
+                for i in 0..5 {
+                    logger.log_at(100 + thread + 4 * i, format!("thread {} entry {}", thread, i)).unwrap();
+                }
+            })
+            .join()
+            .unwrap();
+        }
+        assert_eq!("", fs::read_to_string(path).unwrap());
+
+        logger.flush_all().unwrap();
+        let timestamps: Vec<u64> = crate::read_entries(path).unwrap().iter().map(|e| e.timestamp).collect();
+        assert_eq!((100..120).collect::<Vec<u64>>(), timestamps);
+
+        // same-second entries keep their thread's order, and drop flushes
+        logger.log_at(200, String::from("a")).unwrap();
+        logger.log_at(200, String::from("b")).unwrap();
+        logger.log_at(150, String::from("earlier")).unwrap();
+        logger.flush_all().unwrap();
+        logger.flush_all().unwrap();
+        logger.log_at(300, String::from("on drop")).unwrap();
+        drop(logger);
+
+        let messages: Vec<String> = crate::read_entries(path).unwrap().into_iter().skip(20).map(|e| e.message).collect();
+        assert_eq!(vec!["earlier", "a", "b", "on drop"], messages);
+    }
+
+    #[test]
+    fn thread_buffers_order_within_a_second() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-thread-buffers-millis-test.log");
+        let logger = Logger::new(path).unwrap().with_thread_buffers();
+        for (millis, message) in [(100_900, "late"), (100_100, "early")] {
+            let logger = logger.clone();
+            std::thread::spawn(move || logger.log_time(Duration::from_millis(millis), OutputFormat::Text, String::from(message)).unwrap())
+                .join()
+                .unwrap();
+        }
+        logger.flush_all().unwrap();
+
+        let messages: Vec<String> = crate::read_entries(path).unwrap().into_iter().map(|e| e.message).collect();
+        assert_eq!(vec!["early", "late"], messages);
+    }
+
+    #[test]
+    fn cross_process_lock_is_released_after_each_write() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-lock-test.log");
+        let lock_path = &dir.file("logger-lock-test.log.lock");
+        let logger = Logger::new(path).unwrap().cross_process_lock(true);
+
+        let held = FileLock::acquire(Path::new(path)).unwrap();
+        std::thread::scope(|scope| {
+            let writer = scope.spawn(|| logger.log(String::from("after the lock")).unwrap());
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            assert!(!writer.is_finished());
+            assert_eq!("", fs::read_to_string(path).unwrap());
+            drop(held);
+        });
+
+        assert_eq!(1, crate::read_entries(path).unwrap().len());
+        assert!(!Path::new(lock_path).exists());
+    }
+
+    #[test]
+    fn prepend_puts_newest_first() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-prepend-test.log");
+        fs::write(path, "\n50 - from an earlier run\n").unwrap();
+        let logger = Logger::new(path).unwrap().with_prepend(true).with_run_separator(true);
+        for (timestamp, message) in [(100, "first"), (200, "second"), (300, "third")] {
+            logger.log_at(timestamp, String::from(message)).unwrap();
+        }
+
+        let timestamps: Vec<u64> = crate::read_entries(path).unwrap().iter().map(|e| e.timestamp).collect();
+        assert_eq!(vec![300, 200, 100, 50], timestamps);
+        let contents = fs::read_to_string(path).unwrap();
+        let separator = contents.find("===== run started").unwrap();
+        assert!(contents.find(" - first").unwrap() < separator && separator < contents.find(" - from an").unwrap());
+    }
+
+    #[test]
+    fn per_process_names() {
+        let cases = [
+            ("errors.log", "errors.48213.log"),
+            ("./logs/nested/errors.log", "./logs/nested/errors.48213.log"),
+            ("my.app.log", "my.app.48213.log"),
+            ("errors.2024-06-01.log", "errors.2024-06-01.48213.log"),
+            ("/var/log/app/errors.log", "/var/log/app/errors.48213.log"),
+        ];
+        for (path, expected) in cases {
+            assert_eq!(PathBuf::from(expected), per_process_path(Path::new(path), 48213));
+        }
+
+        let dir = TempLogDir::new();
+        let shared = &dir.file("logger-per-process-test.log");
+        assert_eq!(Some(Path::new(shared)), Logger::new(shared).unwrap().per_process(false).path());
+        fs::remove_file(shared).unwrap();
+        let logger = Logger::new(shared).unwrap().per_process(true);
+        let own = dir.path().join(format!("logger-per-process-test.{}.log", std::process::id()));
+        assert_eq!(Some(own.as_path()), logger.path());
+        assert!(!Path::new(shared).exists());
+
+        logger.log(String::from("only mine")).unwrap();
+        assert_eq!(1, crate::read_entries(&own.to_string_lossy()).unwrap().len());
+    }
+
+    #[test]
+    fn dedup_window_skips_recent_repeats() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-dedup-test.log");
+        let logger = Logger::new(path).unwrap().with_dedup_window(Duration::from_secs(60));
+        logger.log_at(1_000, String::from("disk full")).unwrap();
+        logger.log_at(1_010, String::from("timeout")).unwrap();
+        logger.log_at(1_059, String::from("disk full")).unwrap();
+        logger.log_at(1_060, String::from("disk full")).unwrap();
+        logger.log_at(1_069, String::from("timeout")).unwrap();
+        logger.log_at(1_070, String::from("timeout")).unwrap();
+
+        let written: Vec<(u64, String)> = crate::read_entries(path).unwrap().into_iter().map(|e| (e.timestamp, e.message)).collect();
+        assert_eq!(
+            vec![
+                (1_000, String::from("disk full")),
+                (1_010, String::from("timeout")),
+                (1_060, String::from("disk full")),
+                (1_070, String::from("timeout")),
+            ],
+            written
+        );
+
+        // whole seconds only, so this would skip nothing
+        match Logger::new(path).unwrap().with_dedup_window(Duration::from_millis(500)).build() {
+            Err(e) if e.is::<ConfigError>() => {}
+            other => panic!("Expected a ConfigError, got {:?}", other.map(|_| ())),
+        }
+        assert!(Logger::new(path).unwrap().with_dedup_window(Duration::from_millis(1_500)).build().is_ok());
+    }
+
+    #[test]
+    fn dedup_window_retries_failed_writes() {
+        let memory = Arc::new(MemoryFileSystem::new());
+        let logger = Logger::new_with_file_system("./mock-logs/dedup.log", memory.clone()).unwrap().with_dedup_window(Duration::from_secs(60));
+        memory.fail(Operation::Append, io::ErrorKind::StorageFull);
+        assert!(logger.log_at(1_000, String::from("disk full")).is_err());
+        memory.stop_failing(Operation::Append);
+        logger.log_at(1_001, String::from("disk full")).unwrap();
+        logger.log_at(1_002, String::from("disk full")).unwrap();
+
+        let contents = memory.contents(Path::new("./mock-logs/dedup.log")).unwrap();
+        assert_eq!("\n1001 - disk full\n", String::from_utf8_lossy(&contents));
+    }
+
+    #[test]
+    fn run_separator_between_runs() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-run-separator-test.log");
+
+        let first_run = Logger::new(path).unwrap().with_run_separator(true);
+        first_run.log(String::from("first run one")).unwrap();
+        first_run.log(String::from("first run two")).unwrap();
+        drop(first_run);
+
+        // the separator shows when the run started, not when it first logged
+        let second_run = Logger::new(path).unwrap().started_at(Duration::from_secs(1717250000)).with_run_separator(true);
+        second_run.log_at(1717250703, String::from("second run one")).unwrap();
+        second_run.log_at(1717250704, String::from("second run two")).unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+        assert_eq!(5, lines.len());
+        assert!(lines[0].ends_with(" - first run one"));
+        assert!(lines[1].ends_with(" - first run two"));
+        assert!(is_run_separator(lines[2]), "not a separator: {}", lines[2]);
+        assert_eq!("===== run started 2024-06-01 13:53:20 (format 1) =====", lines[2]);
+        assert!(lines[3].ends_with(" - second run one"));
+        assert!(lines[4].ends_with(" - second run two"));
+
+        let records: Vec<crate::Record> = crate::entries(path).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(4, records.len());
+        assert!(records.iter().all(|r| matches!(r, crate::Record::Entry(_))));
+    }
+
+    #[test]
+    fn environment_snapshot_before_the_first_entry() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-environment-snapshot-test.log");
+        std::env::set_var("ERRLOG_SNAPSHOT_TEST_SET", "one\ntwo");
+        std::env::remove_var("ERRLOG_SNAPSHOT_TEST_UNSET");
+
+        let logger = Logger::new(path)
+            .unwrap()
+            .with_environment_snapshot("2.1.0", &["ERRLOG_SNAPSHOT_TEST_SET", "ERRLOG_SNAPSHOT_TEST_UNSET"]);
+        logger.log_at(1717250703, String::from("first")).unwrap();
+        logger.log_at(1717250704, String::from("second")).unwrap();
+        drop(logger);
+
+        let cwd = std::env::current_dir().unwrap();
+        let expected = format!(
+            "\n1717250703 - environment\n\
+             \t| version: 2.1.0\n\
+             \t| os: {}\n\
+             \t| arch: {}\n\
+             \t| cwd: {}\n\
+             \t| env ERRLOG_SNAPSHOT_TEST_SET: one\\ntwo\n\
+             \t| env ERRLOG_SNAPSHOT_TEST_UNSET: <unset>\n\
+             \n1717250703 - first\n\
+             \n1717250704 - second\n",
+            std::env::consts::OS,
+            std::env::consts::ARCH,
+            cwd.display()
+        );
+        assert_eq!(expected, fs::read_to_string(path).unwrap());
+    }
+
+    struct Opaque;
+
+    impl fmt::Display for Opaque {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("request failed")
+        }
+    }
+
+    impl fmt::Debug for Opaque {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("Opaque {\n    status: 503,\n}")
+        }
+    }
+
+    impl Error for Opaque {}
+
+    #[test]
+    fn error_debug_after_display() {
+        let dir = TempLogDir::new();
+        let plain_path = &dir.file("logger-error-plain-test.log");
+        let debug_path = &dir.file("logger-error-debug-test.log");
+
+        Logger::new(plain_path).unwrap().log_error(&Opaque).unwrap();
+        Logger::new(debug_path).unwrap().with_error_debug(true).log_error(&Opaque).unwrap();
+
+        let plain = fs::read_to_string(plain_path).unwrap();
+        assert!(plain.ends_with(" - request failed\n"), "{:?}", plain);
+        let debug = fs::read_to_string(debug_path).unwrap();
+        assert!(debug.ends_with(" - request failed | debug: Opaque {\n\t|     status: 503,\n\t| }\n"), "{:?}", debug);
+
+        let records: Vec<crate::Record> = crate::entries(debug_path).unwrap().map(|r| r.unwrap()).collect();
+        match &records[..] {
+            [crate::Record::Entry(entry)] => assert_eq!("request failed | debug: Opaque {\n    status: 503,\n}", entry.message),
+            other => panic!("expected one entry, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn backtraces_are_part_of_the_entry() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-backtrace-test.log");
+        let logger = Logger::new(path).unwrap().capture_backtrace(BacktraceMode::OnError);
+        logger.log(String::from("no backtrace")).unwrap();
+        logger.log_error(&Opaque).unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+        assert!(contents.contains("\n\t| backtrace"), "{:?}", contents);
+        let entries = crate::read_entries(path).unwrap();
+        assert_eq!(2, entries.len());
+        assert_eq!("no backtrace", entries[0].message);
+        let (first, rest) = entries[1].message.split_once('\n').unwrap();
+        assert_eq!("request failed", first);
+        assert!(rest.starts_with("backtrace:"), "{:?}", rest);
+        if rest != "backtrace: unsupported" {
+            assert!(rest.lines().count() > 1, "{:?}", rest);
+        }
+    }
+
+    #[test]
+    fn render_matches_the_next_write() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-render-test.log");
+        let logger = Logger::new(path).unwrap().with_checksums(Checksums::Chained).with_millis_on_collision(true);
+        let at = |secs, millis| SystemTime::UNIX_EPOCH + Duration::from_secs(secs) + Duration::from_millis(millis);
+
+        for (secs, millis, message) in [(100, 0, "first"), (100, 250, "same second"), (101, 0, "two\nlines")] {
+            let rendered = logger.render(at(secs, millis), message).unwrap();
+            logger.log_time(at(secs, millis).duration_since(SystemTime::UNIX_EPOCH).unwrap(), OutputFormat::Text, String::from(message)).unwrap();
+            let contents = fs::read_to_string(path).unwrap();
+            assert!(contents.ends_with(&format!("\n{}\n", rendered)), "{:?} not at the end of {:?}", rendered, contents);
+        }
+        assert!(logger.render(at(100, 250), "x").unwrap().starts_with("100 - x #"));
+        let report = crate::verify_chained(path).unwrap();
+        assert_eq!((3, true, true), (report.checked, report.failed.is_empty(), report.missing.is_empty()));
+    }
+
+    #[test]
+    fn scoped_entries_carry_the_id() {
+        let memory = MemorySink::new();
+        let logger = Logger::to_memory_sink(memory.clone());
+
+        let scope = logger.with_scope("7f3a");
+        scope.log(String::from("started")).unwrap();
+        logger.log(String::from("unrelated")).unwrap();
+        scope.log_error(&Opaque).unwrap();
+        assert!(scope.try_log(String::from("finished")));
+
+        let messages: Vec<String> = memory.entries().into_iter().map(|entry| entry.message).collect();
+        assert_eq!(vec!["req=7f3a started", "unrelated", "req=7f3a request failed", "req=7f3a finished"], messages);
+        assert!(memory.contains(" - req=7f3a finished"));
+
+        memory.clear();
+        assert!(memory.is_empty());
+        logger.log_at(100, String::from("after clear")).unwrap();
+        assert_eq!(vec!["100 - after clear"], memory.lines());
+    }
+
+    #[derive(Clone, Default)]
+    struct Captured(std::sync::Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Logs through `logger` from inside every write it's asked to do.
+    struct Reentrant {
+        logger: std::sync::Arc<std::sync::OnceLock<Logger>>,
+        captured: Captured,
+    }
+
+    impl std::io::Write for Reentrant {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if let Some(logger) = self.logger.get() {
+                logger.log(String::from("nested")).unwrap();
+            }
+            self.captured.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn nested_log_calls_are_skipped() {
+        let captured = Captured::default();
+        let cell = std::sync::Arc::new(std::sync::OnceLock::new());
+        let sink = StdoutSink::from_writer(Reentrant { logger: cell.clone(), captured: captured.clone() });
+        let logger = Logger::to_stdout_sink(sink);
+        cell.set(logger.clone()).unwrap();
+
+        logger.log(String::from("outer")).unwrap();
+        logger.log(String::from("outer again")).unwrap();
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let messages: Vec<String> = output.lines().map(|line| line.parse::<crate::LogEntry>().unwrap().message).collect();
+        assert_eq!(vec!["outer", "outer again"], messages);
+        assert_eq!(None, logger.last_error());
+    }
+
+    #[test]
+    fn stdout_sink_gets_formatted_lines() {
+        let captured = Captured::default();
+        let logger = Logger::to_stdout_sink(StdoutSink::from_writer(captured.clone()));
+        assert!(logger.path().is_none());
+        assert!(logger.entries().is_err());
+
+        logger.log(String::from("to stdout")).unwrap();
+        logger.log(String::from("again")).unwrap();
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let lines: Vec<crate::LogEntry> = output.lines().map(|line| line.parse().unwrap()).collect();
+        assert_eq!(2, lines.len());
+        assert_eq!("to stdout", lines[0].message);
+        assert_eq!("again", lines[1].message);
+        assert!(output.ends_with(" - again\n"));
+    }
+
+    #[test]
+    fn ring_sink_dumps_the_last_entries() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-ring-test.log");
+        let ring = RingSink::new(4);
+        let logger = Logger::to_ring_sink(ring.clone());
+        for i in 0..9 {
+            logger.log_at(100 + i, format!("entry {}", i)).unwrap();
+        }
+        assert_eq!(4, ring.len());
+        assert!(!Path::new(path).exists());
+
+        assert_eq!(4, ring.dump_to(path).unwrap());
+        assert!(ring.is_empty());
+        let contents = fs::read_to_string(path).unwrap();
+        let messages: Vec<String> =
+            contents.lines().filter(|line| !line.is_empty()).map(|line| line.parse::<crate::LogEntry>().unwrap().message).collect();
+        assert_eq!(vec!["entry 5", "entry 6", "entry 7", "entry 8"], messages);
+    }
+
+    #[test]
+    fn build_refuses_conflicting_options() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-build-test.log");
+        let logger = Logger::new(path)
+            .unwrap()
+            .lock_timeout(Duration::from_millis(100))
+            .drop_on_lock_timeout(true)
+            .with_prepend(true)
+            .build()
+            .unwrap();
+        logger.log(String::from("built")).unwrap();
+
+        let conflicts = [
+            Logger::new(path).unwrap().drop_on_lock_timeout(true).build(),
+            Logger::stdout().with_prepend(true).build(),
+            Logger::to_ring_sink(RingSink::new(1)).cross_process_lock(true).build(),
+            logger.clone().with_checksums(Checksums::PerLine).with_prepend(false).build(),
+        ];
+        let messages = [
+            "drop_on_lock_timeout needs a lock_timeout to give up after",
+            "with_prepend needs a log file, but this logger writes to a sink",
+            "cross_process_lock needs a log file, but this logger writes to a sink",
+            "with_checksums was called after the logger was cloned, so it was not applied",
+        ];
+        for (built, message) in conflicts.into_iter().zip(messages) {
+            match built {
+                Err(e) => {
+                    assert!(e.is::<ConfigError>());
+                    assert_eq!(message, e.to_string());
+                }
+                Ok(_) => panic!("Building with conflicting options should have failed: {}", message),
+            }
+        }
+
+        // the clone's late option didn't reach the shared settings
+        logger.log(String::from("still unchecksummed")).unwrap();
+        let messages: Vec<String> = crate::read_entries(path).unwrap().into_iter().map(|e| e.message).collect();
+        assert!(messages.contains(&String::from("still unchecksummed")), "{:?}", messages);
+    }
+
+    #[test]
+    fn long_messages_are_split() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-split-test.log");
+        let logger = Logger::new(path).unwrap().with_max_message_len(10);
+        logger.log_at(100, String::from("short")).unwrap();
+        logger.log_at(200, format!("{}{}{}", "a".repeat(10), "b".repeat(10), "☃☃☃☃")).unwrap();
+        logger.log_at(300, "c".repeat(10)).unwrap();
+
+        let messages: Vec<String> = crate::read_entries(path).unwrap().into_iter().map(|entry| entry.message).collect();
+        assert_eq!(
+            vec!["short", "(1/4) aaaaaaaaaa", "(2/4) bbbbbbbbbb", "(3/4) ☃☃☃", "(4/4) ☃", "cccccccccc"],
+            messages
+        );
+        assert_eq!(vec!["☃", "☃"], split_message("☃☃", 2));
+    }
+
+    /// Refuses every entry.
+    #[derive(Debug)]
+    struct Failing;
+
+    impl Sink for Failing {
+        fn write_entry(&mut self, _entry: &LogEntry) -> Result<(), Box<dyn Error>> {
+            Err(Box::new(io::Error::other("channel closed")))
+        }
+    }
+
+    #[test]
+    fn custom_sinks_get_the_same_entries() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-sinks-test.log");
+        let collected = MemorySink::new();
+        let sinks: Vec<Box<dyn Sink>> = vec![Box::new(Failing), Box::new(crate::FileSink::new(path).unwrap()), Box::new(collected.clone())];
+        let logger = Logger::to_sinks(sinks);
+
+        for (ts, message) in [(100, "first"), (200, "[E0042] - coded"), (300, "two\nlines")] {
+            match logger.log_at(ts, String::from(message)) {
+                Err(e) => assert_eq!("failed to write to 1 of 3 sinks: sink 1: channel closed", e.to_string()),
+                Ok(()) => panic!("The failing sink should have been reported."),
+            }
+        }
+        assert!(logger.last_error().is_some_and(|e| e.contains("sink 1: channel closed")));
+        assert!(logger.flush_all().is_ok());
+
+        let written = crate::read_entries(path).unwrap();
+        assert_eq!(3, written.len());
+        assert_eq!(written, collected.entries());
+        assert_eq!(Some(42), written[1].code);
+
+        // a sink next to a logger's own file
+        fs::remove_file(path).unwrap();
+        let collected = MemorySink::new();
+        let logger = Logger::new(path).unwrap().also_sink(Box::new(Failing)).also_sink(Box::new(collected.clone()));
+        let failure = logger.log_at(400, String::from("still written")).unwrap_err();
+        assert!(failure.is::<SinkError>());
+        assert_eq!(crate::read_entries(path).unwrap(), collected.entries());
+    }
+
+    #[test]
+    fn fields_in_both_formats() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-fields-test.log");
+        let logger = Logger::new(path).unwrap();
+        let fields = vec![(String::from("user"), String::from("ann")), (String::from("path"), String::from("/a b"))];
+        logger.log_time_with(Duration::from_secs(100), OutputFormat::Text, String::from("denied"), fields.clone()).unwrap();
+        logger.log_time_with(Duration::from_secs(200), OutputFormat::Json, String::from("denied"), fields.clone()).unwrap();
+
+        for key in ["", "two words", "a=b", "new\nline", "v", "timestamp", "code", "message"] {
+            let bad = vec![(String::from(key), String::from("x"))];
+            match logger.log_fields(OutputFormat::Text, String::from("never written"), &bad) {
+                Err(e) => assert!(e.is::<FieldNameError>(), "{}", e),
+                Ok(()) => panic!("{:?} should have been refused.", key),
+            }
+        }
+
+        let contents = fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+        assert_eq!(2, lines.len());
+        assert_eq!("100 - denied user=ann path=\"/a b\"", lines[0]);
+        let entry = crate::json::entry_from_json(lines[1]).unwrap();
+        assert_eq!("denied", entry.message);
+        assert_eq!(Some(&String::from("ann")), entry.fields.get("user"));
+        assert_eq!(Some(&String::from("/a b")), entry.fields.get("path"));
+    }
+
+    #[test]
+    fn json_entries_in_a_text_log() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-log-as-test.log");
+        let logger = Logger::new(path).unwrap().with_checksums(Checksums::PerLine);
+        logger.log_at(100, String::from("plain")).unwrap();
+        logger.log_time(Duration::from_secs(200), OutputFormat::Json, String::from("[E0042] - user \"ann\"\nlogged in")).unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].starts_with("100 - plain #"));
+        assert_eq!(r#"{"v":1,"timestamp":200,"code":42,"message":"user \"ann\"\nlogged in"}"#, lines[1]);
+
+        let records: Vec<crate::Record> = crate::entries(path).unwrap().map(Result::unwrap).collect();
+        assert!(matches!(&records[1], crate::Record::Malformed { raw, .. } if raw == lines[1]));
+        assert_eq!("user \"ann\"\nlogged in", LogEntry::from_json(lines[1]).unwrap().message);
+    }
+
+    #[test]
+    fn journald_prefix_on_one_line() {
+        let captured = Captured::default();
+        let logger = Logger::to_stdout_sink(StdoutSink::from_writer(captured.clone()).with_journald_prefix(true));
+        logger.log_at(100, String::from("plain")).unwrap();
+        logger.log_at(200, String::from("first\nsecond")).unwrap();
+        logger.log_at(300, String::from("crlf\r\nbreak")).unwrap();
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert_eq!("<3>100 - plain\n<3>200 - first\\nsecond\n<3>300 - crlf\\r\\nbreak\n", output);
+    }
+
+    #[test]
+    fn nul_terminated_records() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-nul-test.log");
+        let logger = Logger::new(path).unwrap().with_terminator(b"\0");
+        logger.log(String::from("first line\nsecond line")).unwrap();
+        logger.log(String::from("another")).unwrap();
+
+        let contents = fs::read(path).unwrap();
+        let records: Vec<&[u8]> = contents.split(|&b| b == 0).filter(|r| !r.is_empty()).collect();
+        assert_eq!(2, records.len());
+        assert!(records[0].ends_with(b" - first line\nsecond line"));
+        assert!(records[1].ends_with(b" - another"));
+
+        let messages: Vec<String> = logger
+            .entries()
+            .unwrap()
+            .map(|r| match r.unwrap() {
+                crate::Record::Entry(entry) => entry.message,
+                other => panic!("Unexpected record {:?}", other),
+            })
+            .collect();
+        assert_eq!(vec!["first line\nsecond line", "another"], messages);
+    }
+
+    #[test]
+    fn held_locks_time_out() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-lock-timeout-test.log");
+        let logger = Logger::new(path).unwrap().lock_timeout(Duration::from_millis(100));
+        let held = logger.inner.write_lock.lock().unwrap();
+
+        let writer = logger.clone();
+        let (result, waited) = thread::spawn(move || {
+            let started = Instant::now();
+            let result = writer.log(String::from("stuck")).map_err(|e| e.to_string());
+            (result, started.elapsed())
+        })
+        .join()
+        .unwrap();
+        drop(held);
+
+        match result {
+            Err(e) => assert!(e.starts_with("timed out after 100ms waiting for the logger's write lock"), "{}", e),
+            Ok(_) => panic!("Logging under a held lock should have timed out."),
+        }
+        assert!(waited >= Duration::from_millis(100) && waited < Duration::from_secs(2), "{:?}", waited);
+        logger.log(String::from("free again")).unwrap();
+
+        // best effort: dropped and counted instead
+        let lock_file = &dir.file("logger-lock-timeout-drop-test.log.lock");
+        let logger = Logger::new(&dir.file("logger-lock-timeout-drop-test.log"))
+            .unwrap()
+            .cross_process_lock(true)
+            .lock_timeout(Duration::from_millis(50))
+            .drop_on_lock_timeout(true);
+        fs::write(lock_file, format!("{} 1000\n", std::process::id())).unwrap();
+        logger.log(String::from("dropped")).unwrap();
+        assert_eq!(1, logger.dropped_count());
+        fs::remove_file(lock_file).unwrap();
+        logger.log(String::from("kept")).unwrap();
+        assert_eq!(1, logger.dropped_count());
+        let messages: Vec<String> = logger
+            .entries()
+            .unwrap()
+            .filter_map(|r| match r.unwrap() {
+                crate::Record::Entry(entry) => Some(entry.message),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec!["1 entry dropped", "kept"], messages);
+    }
+
+    #[test]
+    fn try_log_drops_instead_of_waiting() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-try-log-test.log");
+        let logger = Logger::new(path).unwrap();
+        assert!(logger.try_log(String::from("uncontended")));
+
+        let held = logger.inner.write_lock.lock().unwrap();
+        let writer = logger.clone();
+        let written = thread::spawn(move || [writer.try_log(String::from("busy")), writer.try_log(String::from("busier"))])
+            .join()
+            .unwrap();
+        drop(held);
+        assert_eq!([false, false], written);
+        assert_eq!(2, logger.dropped_count());
+        assert_eq!(None, logger.last_error());
+
+        logger.log_at(500, String::from("after")).unwrap();
+        logger.log_at(501, String::from("and again")).unwrap();
+        assert_eq!(2, logger.dropped_count());
+        let found = crate::read_entries(path).unwrap();
+        let messages: Vec<&str> = found.iter().map(|entry| entry.message.as_str()).collect();
+        assert_eq!(vec!["uncontended", "2 entries dropped", "after", "and again"], messages);
+        assert_eq!(500, found[1].timestamp);
+    }
+
+    #[test]
+    fn emergency_write_ignores_the_logger_lock() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-emergency-test.log");
+        let logger = Logger::new(path).unwrap().with_checksums(Checksums::PerLine);
+        logger.log(String::from("before")).unwrap();
+
+        // would deadlock if it waited for the lock this thread holds
+        let held = logger.inner.write_lock.lock().unwrap();
+        crate::emergency_write(path, "last words").unwrap();
+        drop(held);
+
+        let found = crate::read_entries(path).unwrap();
+        assert_eq!(2, found.len());
+        assert_eq!("last words", found[1].message);
+        assert_eq!(None, found[1].checksum);
+        let contents = fs::read_to_string(path).unwrap();
+        assert!(contents.ends_with(&format!("\n{} - last words\n", found[1].timestamp)));
+    }
+
+    #[test]
+    fn millis_only_on_collisions() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-millis-test.log");
+        let logger = Logger::new(path).unwrap().with_millis_on_collision(true);
+        logger.log_time(Duration::from_millis(100_250), OutputFormat::Text, String::from("first")).unwrap();
+        logger.log_time(Duration::from_millis(100_007), OutputFormat::Text, String::from("same second")).unwrap();
+        logger.log_time(Duration::from_millis(101_500), OutputFormat::Text, String::from("next second")).unwrap();
+
+        assert_eq!("\n100 - first\n\n100.007 - same second\n\n101 - next second\n", fs::read_to_string(path).unwrap());
+        let found = crate::read_entries(path).unwrap();
+        let stamps: Vec<(u64, Option<u16>)> = found.iter().map(|entry| (entry.timestamp, entry.millis)).collect();
+        assert_eq!(vec![(100, None), (100, Some(7)), (101, None)], stamps);
+        assert_eq!("100.007 - same second", found[1].to_string());
+    }
+
+    #[test]
+    fn tcp_sink_reconnects_without_duplicates() {
+        use std::io::{BufRead, BufReader};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let read_until = |reader: &mut BufReader<std::net::TcpStream>, last: &str| -> Vec<String> {
+            let mut lines = Vec::new();
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let message = line.trim_end().parse::<LogEntry>().unwrap().message;
+                lines.push(message.clone());
+                if message == last {
+                    return lines;
+                }
+            }
+        };
+
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-tcp-test.log");
+        let sink = crate::TcpSink::new(&address).with_max_backoff(Duration::from_millis(20));
+        let dropped = sink.dropped_counter();
+        let logger = Logger::new(path).unwrap().also_sink(Box::new(sink));
+        logger.log_at(100, String::from("a")).unwrap();
+        logger.log_at(101, String::from("two\nlines")).unwrap();
+
+        let (first, _) = listener.accept().unwrap();
+        first.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut received = read_until(&mut BufReader::new(first), "two\\nlines");
+        assert_eq!(vec!["a", "two\\nlines"], received);
+
+        // the collector went away; keep logging until the sink is back
+        listener.set_nonblocking(true).unwrap();
+        let mut sent = 0;
+        let second = loop {
+            logger.log_at(200 + sent, format!("after {}", sent)).unwrap();
+            sent += 1;
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(5)),
+                Err(e) => panic!("accept failed: {}", e),
+            }
+            assert!(sent < 1000, "the sink never reconnected");
+        };
+        second.set_nonblocking(false).unwrap();
+        second.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        logger.log_at(999, String::from("last")).unwrap();
+        received.extend(read_until(&mut BufReader::new(second), "last"));
+
+        let mut unique = received.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(received.len(), unique.len(), "duplicated entries: {:?}", received);
+        assert_eq!(Some(&String::from("last")), received.last());
+        assert_eq!(0, dropped.load(Ordering::Relaxed));
+        assert_eq!(sent as usize + 3, crate::read_entries(path).unwrap().len());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unix_sinks_survive_the_socket_going_away() {
+        use std::io::{BufRead, BufReader};
+        use std::os::unix::net::{UnixDatagram, UnixListener};
+        use crate::{UnixSink, UnixSocketKind};
+
+        let temp = TempLogDir::new();
+        let dir = &temp.file("logger-unix-sink");
+        let socket = format!("{}/collector.sock", dir);
+        fs::create_dir_all(dir).unwrap();
+        match UnixSink::new(&socket, UnixSocketKind::Stream) {
+            Err(e) => assert!(e.is::<crate::SocketNotFoundError>()),
+            Ok(_) => panic!("A sink for a missing socket should have failed."),
+        }
+
+        let read_line = |reader: &mut BufReader<std::os::unix::net::UnixStream>| {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            line.trim_end().parse::<LogEntry>().unwrap().message
+        };
+
+        let listener = UnixListener::bind(&socket).unwrap();
+        let sink = UnixSink::new(&socket, UnixSocketKind::Stream).unwrap().with_max_backoff(Duration::from_millis(20));
+        let dropped = sink.dropped_counter();
+        let logger = Logger::to_sinks(vec![Box::new(sink)]);
+        logger.log_at(100, String::from("before")).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let mut received = vec![read_line(&mut BufReader::new(stream))];
+
+        // the collector goes away, socket file and all, and comes back
+        drop(listener);
+        fs::remove_file(&socket).unwrap();
+        for i in 0..5 {
+            logger.log_at(200 + i, format!("while gone {}", i)).unwrap();
+        }
+        let listener = UnixListener::bind(&socket).unwrap();
+        listener.set_nonblocking(true).unwrap();
+        let mut sent = 0;
+        let stream = loop {
+            logger.log_at(300 + sent, format!("after {}", sent)).unwrap();
+            sent += 1;
+            match listener.accept() {
+                Ok((stream, _)) => break stream,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(5)),
+                Err(e) => panic!("accept failed: {}", e),
+            }
+            assert!(sent < 1000, "the sink never reconnected");
+        };
+        stream.set_nonblocking(false).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        logger.log_at(999, String::from("last")).unwrap();
+        let mut reader = BufReader::new(stream);
+        while received.last().map(String::as_str) != Some("last") {
+            received.push(read_line(&mut reader));
+        }
+
+        let mut unique = received.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(received.len(), unique.len(), "duplicated entries: {:?}", received);
+        assert!(received.contains(&String::from("while gone 0")), "{:?}", received);
+        assert_eq!(0, dropped.load(Ordering::Relaxed));
+        drop(listener);
+        fs::remove_file(&socket).unwrap();
+
+        // datagrams
+        let collector = UnixDatagram::bind(&socket).unwrap();
+        collector.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let logger = Logger::to_sinks(vec![Box::new(UnixSink::new(&socket, UnixSocketKind::Datagram).unwrap())]);
+        logger.log_at(100, String::from("[E0042] - as a datagram")).unwrap();
+        let mut buf = [0; 1024];
+        let len = collector.recv(&mut buf).unwrap();
+        assert_eq!("100 - [E0042] - as a datagram\n", std::str::from_utf8(&buf[..len]).unwrap());
+    }
+
+    #[test]
+    fn entries_are_also_sent_to_syslog() {
+        let collector = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        collector.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        let address = collector.local_addr().unwrap().to_string();
+        let receive = || {
+            let mut buf = [0; 4096];
+            let len = collector.recv(&mut buf).unwrap();
+            String::from_utf8(buf[..len].to_vec()).unwrap()
+        };
+
+        let dir = TempLogDir::new();
+        let path = &dir.file("logger-syslog-udp-test.log");
+        let options = crate::SyslogOptions { app_name: Some(String::from("errlog-test")), ..crate::SyslogOptions::default() };
+        let sink = SyslogUdpSink::connect(&address, options.clone()).unwrap();
+        let logger = Logger::new(path).unwrap().also_syslog_udp(sink);
+        logger.log_at(1_709_820_543, String::from("[E0042] - disk full")).unwrap();
+        assert_eq!("<11>1 2024-03-07T14:09:03Z - errlog-test - E0042 - disk full", receive());
+
+        // cut at a character boundary, with a marker
+        let sink = SyslogUdpSink::connect(&address, options).unwrap().with_max_datagram(81);
+        let logger = Logger::new(path).unwrap().also_syslog_udp(sink);
+        logger.log_at(1_709_820_543, "☃".repeat(40)).unwrap();
+        let short = receive();
+        assert!(short.len() <= 81 && short.ends_with("☃...[truncated]"), "{}", short);
+
+        // too big for any datagram: the file write still succeeds
+        let sink = SyslogUdpSink::connect(&address, crate::SyslogOptions::default()).unwrap().with_max_datagram(100_000);
+        let logger = Logger::new(path).unwrap().also_syslog_udp(sink);
+        logger.log(String::from("x").repeat(70_000)).unwrap();
+        assert_eq!(1, logger.failed_syslog_sends());
+        assert_eq!(3, crate::read_entries(path).unwrap().len());
+    }
+
+    #[test]
+    fn writes_go_through_the_file_system() {
+        let memory = Arc::new(MemoryFileSystem::new());
+        let logger = Logger::new_with_file_system("./mock-logs/app.log", memory.clone()).unwrap();
+        logger.log_at(100, String::from("in memory")).unwrap();
+        logger.log_at(200, String::from("still in memory")).unwrap();
+
+        assert_eq!(vec![PathBuf::from("./mock-logs")], memory.dirs());
+        let contents = memory.contents(Path::new("./mock-logs/app.log")).unwrap();
+        assert_eq!("\n100 - in memory\n\n200 - still in memory\n", String::from_utf8_lossy(&contents));
+        assert!(!Path::new("./mock-logs").exists());
+
+        let denied = MemoryFileSystem::new();
+        denied.fail(Operation::CreateDir, io::ErrorKind::PermissionDenied);
+        match Logger::new_with_file_system("./mock-logs/app.log", Arc::new(denied)) {
+            Err(e) => assert!(e.downcast_ref::<NotWritableError>().is_some()),
+            Ok(_) => panic!("A directory that can't be made should have failed."),
+        }
+    }
+
+    #[test]
+    fn failed_rename_is_surfaced() {
+        let memory = Arc::new(MemoryFileSystem::new());
+        memory.add_dir(Path::new("./mock-logs"));
+        memory.add_file(Path::new("./mock-logs/app.log"), b"\n100 - old\n");
+        memory.fail(Operation::Rename, io::ErrorKind::Other);
+        let logger = Logger::new_with_file_system("./mock-logs/app.log", memory.clone()).unwrap().with_prepend(true);
+
+        match logger.log_at(200, String::from("new")) {
+            Err(e) => assert_eq!("failed to rename a new version over ./mock-logs/app.log: scripted Rename failure", e.to_string()),
+            Ok(_) => panic!("The rename should have failed."),
+        }
+        assert!(logger.last_error().is_some_and(|e| e.ends_with(": scripted Rename failure")));
+        // the log is untouched and the temporary file is gone
+        assert_eq!(Some(b"\n100 - old\n".to_vec()), memory.contents(Path::new("./mock-logs/app.log")));
+        assert_eq!(None, memory.contents(Path::new("./mock-logs/app.log.tmp")));
+    }
+
+    #[test]
+    fn logs_to_a_given_file() {
+        let dir = TempLogDir::new();
+        // any name will do, the logger never sees it
+        let path = &dir.file("handed-over.txt");
+        let file = fs::OpenOptions::new().create(true).append(true).open(path).unwrap();
+        let logger = Logger::from_file(file);
+        logger.log_at(100, String::from("first")).unwrap();
+        logger.log_at(101, String::from("two\nlines")).unwrap();
+
+        assert_eq!("\n100 - first\n\n101 - two\n\t| lines\n", fs::read_to_string(path).unwrap());
+        assert_eq!(None, logger.path());
+        let file = fs::File::open(path).unwrap();
+        assert!(Logger::from_file(file).with_prepend(true).build().is_err());
+    }
+
+    #[test]
+    fn secrets_are_redacted_before_any_sink() {
+        let sink = MemorySink::new();
+        let logger = Logger::to_memory_sink(sink.clone()).with_redaction(&["hunter2", "token=*"]);
+        let message = String::from("connect to postgres://app:hunter2@db failed, retry with token=abc123");
+        let fields = [(String::from("password"), String::from("hunter2")), (String::from("auth"), String::from("token=xyz9 ok"))];
+        logger.log_fields(OutputFormat::Text, message, &fields).unwrap();
+
+        let lines = sink.lines();
+        assert_eq!(1, lines.len());
+        for secret in ["hunter2", "abc123", "xyz9"] {
+            assert!(!lines[0].contains(secret), "{} leaked into {}", secret, lines[0]);
+        }
+        assert!(lines[0].ends_with(
+            " - connect to postgres://app:[REDACTED]@db failed, retry with [REDACTED] password=[REDACTED] auth=\"[REDACTED] ok\""
+        ), "{}", lines[0]);
+
+        // the snapshot is the logger's own entry, but holds the caller's values
+        std::env::set_var("ERRLOG_REDACTION_TEST_URL", "postgres://app@db?password=s3cret");
+        let snapshot_sink = MemorySink::new();
+        let logger = Logger::to_memory_sink(snapshot_sink.clone())
+            .with_redaction(&["password=*"])
+            .with_environment_snapshot("1.0", &["ERRLOG_REDACTION_TEST_URL"]);
+        logger.log(String::from("started")).unwrap();
+        let lines = snapshot_sink.lines();
+        assert_eq!(2, lines.len());
+        assert!(lines[0].contains("env ERRLOG_REDACTION_TEST_URL: postgres://app@db?[REDACTED]"), "{}", lines[0]);
+        assert!(!lines.iter().any(|line| line.contains("s3cret")), "{:?}", lines);
+
+        let logger = Logger::to_memory_sink(sink.clone()).with_redactor(Box::new(|text| text.replace(char::is_numeric, "#")));
+        logger.log(String::from("card 4111 1111")).unwrap();
+        assert!(sink.lines()[1].ends_with(" - card #### ####"), "{}", sink.lines()[1]);
+        assert!(logger.render(SystemTime::UNIX_EPOCH, "pin 1234").unwrap().ends_with(" - pin ####"));
+    }
+
+    #[test]
+    fn heartbeats_between_entries() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("heartbeat-test.log");
+        let logger = Logger::new(path).unwrap().with_heartbeat(Heartbeat::EveryEntries(5));
+        for i in 0..10 {
+            logger.log_at(100 + i, format!("entry {}", i)).unwrap();
+        }
+
+        let contents = fs::read_to_string(path).unwrap();
+        let lines: Vec<&str> = contents.lines().filter(|line| !line.is_empty()).collect();
+        let beats: Vec<usize> = (0..lines.len()).filter(|&i| is_heartbeat(lines[i])).collect();
+        assert_eq!(vec![5, 11], beats);
+        assert!(lines[5].starts_with("===== heartbeat 1970-01-01 00:01:44 entries=5 uptime="), "{}", lines[5]);
+        assert!(lines[11].starts_with("===== heartbeat 1970-01-01 00:01:49 entries=10 uptime="), "{}", lines[11]);
+        assert_eq!(10, crate::read_entries(path).unwrap().len());
+        assert_eq!(Some(10), crate::info(path, true).unwrap().entry_count);
+        assert!(crate::top_messages(path, 20, false).unwrap().iter().all(|(message, _)| message.starts_with("entry ")));
+
+        let memory = Arc::new(MemoryFileSystem::new());
+        let sink = MemorySink::new();
+        let logger = Logger::new_with_file_system("./mock-logs/heartbeat.log", memory.clone())
+            .unwrap()
+            .with_heartbeat(Heartbeat::EveryDuration(Duration::from_secs(60)))
+            .also_sink(Box::new(sink.clone()));
+        for timestamp in [1000, 1030, 1060, 1070, 1200] {
+            logger.log_at(timestamp, String::from("tick")).unwrap();
+        }
+        let contents = String::from_utf8(memory.contents(Path::new("./mock-logs/heartbeat.log")).unwrap()).unwrap();
+        let beats: Vec<&str> = contents.lines().filter(|line| is_heartbeat(line)).collect();
+        assert_eq!(2, beats.len());
+        assert!(beats[0].starts_with("===== heartbeat 1970-01-01 00:17:40 entries=3 "), "{}", beats[0]);
+        assert!(beats[1].starts_with("===== heartbeat 1970-01-01 00:20:00 entries=5 "), "{}", beats[1]);
+        assert_eq!(5, sink.entries().len());
+
+        for heartbeat in [Heartbeat::EveryEntries(0), Heartbeat::EveryDuration(Duration::ZERO)] {
+            match Logger::to_memory_sink(MemorySink::new()).with_heartbeat(heartbeat).build() {
+                Err(e) if e.is::<ConfigError>() => {}
+                other => panic!("Expected a ConfigError for {:?}, got {:?}", heartbeat, other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn log_here_reports_the_caller() {
+        #[track_caller]
+        fn tracked(logger: &Logger) {
+            logger.log_here(String::from("tracked")).unwrap();
+        }
+        fn untracked(logger: &Logger) -> u32 {
+            let line = line!() + 1;
+            logger.log_here(String::from("untracked")).unwrap();
+            line
+        }
+
+        let sink = MemorySink::new();
+        let logger = Logger::to_memory_sink(sink.clone());
+        let (here, _) = (Location::caller(), logger.log_here(String::from("direct")).unwrap());
+        let (through, _) = (Location::caller(), tracked(&logger));
+        let inside = untracked(&logger);
+
+        let messages: Vec<String> = sink.entries().into_iter().map(|entry| entry.message).collect();
+        assert_eq!(
+            vec![
+                format!("direct location={}:{}", file!(), here.line()),
+                format!("tracked location={}:{}", file!(), through.line()),
+                format!("untracked location={}:{}", file!(), inside),
+            ],
+            messages
+        );
+        assert_eq!(file!(), here.file());
+    }
+
+    /// The plain format as `FORMAT_VERSION` 1 lays it out, against files
+    /// that only change by hand. A change that fails this needs a new
+    /// version and new golden files, not edited ones.
+    #[test]
+    fn golden_format_v1() {
+        assert_eq!(1, FORMAT_VERSION, "a new format version needs golden files of its own");
+        let dir = TempLogDir::new();
+
+        let path = &dir.file("golden.log");
+        fs::write(path, "\n1717250000 - from an earlier run\n").unwrap();
+        let logger = Logger::new(path)
+            .unwrap()
+            .started_at(Duration::from_secs(1717250703))
+            .with_run_separator(true)
+            .with_millis_on_collision(true);
+        logger.log_at(1717250703, String::from("plain entry")).unwrap();
+        logger.log_time(Duration::from_millis(1717250703250), OutputFormat::Text, String::from("same second")).unwrap();
+        logger.log_at(1717250710, String::from("[E0042] - disk full")).unwrap();
+        logger.log_at(1717250711, String::from("two\nlines\r\nand three")).unwrap();
+        let fields = vec![(String::from("user"), String::from("ada")), (String::from("path"), String::from("a b"))];
+        logger.log_time_with(Duration::from_secs(1717250712), OutputFormat::Text, String::from("with fields"), fields).unwrap();
+        assert_eq!(include_bytes!("../tests/golden/format-v1.log").as_slice(), fs::read(path).unwrap());
+
+        let path = &dir.file("golden-checksums.log");
+        let logger = Logger::new(path).unwrap().with_checksums(Checksums::Chained);
+        logger.log_at(1717250703, String::from("first")).unwrap();
+        logger.log_at(1717250704, String::from("second")).unwrap();
+        assert_eq!(include_bytes!("../tests/golden/format-v1-checksums.log").as_slice(), fs::read(path).unwrap());
+
+        let path = &dir.file("golden-compacted.log");
+        fs::write(path, include_bytes!("../tests/golden/format-v1.log")).unwrap();
+        let written = crate::read_entries(path).unwrap();
+        crate::compact_log(path).unwrap();
+        assert_eq!(include_bytes!("../tests/golden/format-v1-compacted.log").as_slice(), fs::read(path).unwrap());
+        assert_eq!(written, crate::read_entries(path).unwrap());
+    }
+
+    #[test]
+    fn failed_appends_keep_their_kind_and_context() {
+        let memory = Arc::new(MemoryFileSystem::new());
+        let logger = Logger::new_with_file_system("./mock-logs/app.log", memory.clone()).unwrap();
+        logger.log_at(100, String::from("fits")).unwrap();
+
+        memory.fail(Operation::Append, io::ErrorKind::StorageFull);
+        let e = logger.log_at(200, String::from("disk full")).unwrap_err();
+        assert_eq!("failed to append to ./mock-logs/app.log: scripted Append failure", e.to_string());
+        assert_eq!(io::ErrorKind::StorageFull, crate::into_io_error(e).kind());
+
+        memory.stop_failing(Operation::Append);
+        memory.fail(Operation::OpenAppend, io::ErrorKind::PermissionDenied);
+        let e = logger.log_at(300, String::from("denied")).unwrap_err();
+        assert_eq!("failed to open ./mock-logs/app.log: scripted OpenAppend failure", e.to_string());
+
+        memory.stop_failing(Operation::OpenAppend);
+        logger.log_at(400, String::from("recovered")).unwrap();
+        let contents = memory.contents(Path::new("./mock-logs/app.log")).unwrap();
+        assert_eq!("\n100 - fits\n\n400 - recovered\n", String::from_utf8_lossy(&contents));
+    }
+
+    #[test]
+    fn failed_appends_leave_the_chain_alone() {
+        let memory = Arc::new(MemoryFileSystem::new());
+        let logger = Logger::new_with_file_system("./mock-logs/chained.log", memory.clone()).unwrap().with_checksums(Checksums::Chained);
+        logger.log_at(100, String::from("first")).unwrap();
+        memory.fail(Operation::Append, io::ErrorKind::StorageFull);
+        assert!(logger.log_at(200, String::from("lost")).is_err());
+        memory.stop_failing(Operation::Append);
+        logger.log_at(300, String::from("after")).unwrap();
+
+        let dir = TempLogDir::new();
+        let path = &dir.file("chained.log");
+        fs::write(path, memory.contents(Path::new("./mock-logs/chained.log")).unwrap()).unwrap();
+        let report = crate::verify_chained(path).unwrap();
+        assert_eq!(2, report.checked);
+        assert!(report.is_clean(), "{:?}", report);
+    }
+}
@@ -0,0 +1,149 @@
+//! A `FileSystem` in memory whose operations can be made to fail, for
+//! tests. Built for the crate's own tests and with the `test-util` feature.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::filesystem::{FileMetadata, FileSystem};
+
+/// A `FileSystem` method, or writing through a handle `open_append` gave,
+/// for `MemoryFileSystem::fail`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+    CreateDir,
+    OpenAppend,
+    /// Writes to a file opened with `open_append`, e.g. to act out a full
+    /// disk.
+    Append,
+    Metadata,
+    Read,
+    Write,
+    Rename,
+    Remove,
+}
+
+/// Files and directories kept in memory, for testing how code copes when
+/// the disk misbehaves: an operation set to `fail` returns an error of the
+/// given kind every time until `stop_failing`.
+#[derive(Debug, Default)]
+pub struct MemoryFileSystem {
+    dirs: Mutex<Vec<PathBuf>>,
+    files: Mutex<HashMap<PathBuf, Vec<u8>>>,
+    failures: Mutex<HashMap<Operation, io::ErrorKind>>,
+}
+
+impl MemoryFileSystem {
+    pub fn new() -> MemoryFileSystem {
+        MemoryFileSystem::default()
+    }
+
+    /// Makes every later `operation` fail with an error of `kind`.
+    pub fn fail(&self, operation: Operation, kind: io::ErrorKind) {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner()).insert(operation, kind);
+    }
+
+    /// Undoes `fail` for `operation`.
+    pub fn stop_failing(&self, operation: Operation) {
+        self.failures.lock().unwrap_or_else(|e| e.into_inner()).remove(&operation);
+    }
+
+    /// Adds the directory `path` as if it had been there all along.
+    pub fn add_dir(&self, path: &Path) {
+        self.dirs.lock().unwrap_or_else(|e| e.into_inner()).push(path.to_path_buf());
+    }
+
+    /// Puts `contents` at `path` as if it had been there all along.
+    pub fn add_file(&self, path: &Path, contents: &[u8]) {
+        self.files.lock().unwrap_or_else(|e| e.into_inner()).insert(path.to_path_buf(), contents.to_vec());
+    }
+
+    /// The directories made or added so far, in that order.
+    pub fn dirs(&self) -> Vec<PathBuf> {
+        self.dirs.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// What's at `path` right now, `None` if there's no file.
+    pub fn contents(&self, path: &Path) -> Option<Vec<u8>> {
+        self.files.lock().unwrap_or_else(|e| e.into_inner()).get(path).cloned()
+    }
+
+    fn check(&self, operation: Operation) -> io::Result<()> {
+        match self.failures.lock().unwrap_or_else(|e| e.into_inner()).get(&operation) {
+            Some(&kind) => Err(io::Error::new(kind, format!("scripted {:?} failure", operation))),
+            None => Ok(()),
+        }
+    }
+
+    fn files(&self) -> MutexGuard<'_, HashMap<PathBuf, Vec<u8>>> {
+        self.files.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A file a `MemoryFileSystem` opened for appending.
+struct MemoryFile<'a> {
+    file_system: &'a MemoryFileSystem,
+    path: PathBuf,
+}
+
+impl Write for MemoryFile<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.file_system.check(Operation::Append)?;
+        self.file_system.files().entry(self.path.clone()).or_default().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl FileSystem for MemoryFileSystem {
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        self.check(Operation::CreateDir)?;
+        self.add_dir(path);
+        Ok(())
+    }
+
+    fn open_append<'a>(&'a self, path: &Path) -> io::Result<Box<dyn Write + 'a>> {
+        self.check(Operation::OpenAppend)?;
+        self.files().entry(path.to_path_buf()).or_default();
+        Ok(Box::new(MemoryFile { file_system: self, path: path.to_path_buf() }))
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        self.check(Operation::Metadata)?;
+        if self.dirs().iter().any(|dir| dir == path) {
+            return Ok(FileMetadata { len: 0, is_dir: true });
+        }
+        match self.files().get(path) {
+            Some(bytes) => Ok(FileMetadata { len: bytes.len() as u64, is_dir: false }),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        }
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.check(Operation::Read)?;
+        self.contents(path).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+
+    fn write(&self, path: &Path, contents: &[u8]) -> io::Result<()> {
+        self.check(Operation::Write)?;
+        self.add_file(path, contents);
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.check(Operation::Rename)?;
+        let mut files = self.files();
+        let contents = files.remove(from).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        files.insert(to.to_path_buf(), contents);
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> io::Result<()> {
+        self.check(Operation::Remove)?;
+        self.files().remove(path).map(|_| ()).ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))
+    }
+}
@@ -0,0 +1,204 @@
+//! Combining several logs into one chronological log.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::error::Error;
+use std::fs;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::reader::{entries, Entries, LogEntry, Record};
+
+/// What `merge_with` does with lines that don't parse as entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MalformedLines {
+    /// Write them right after the entry that preceded them in their own
+    /// file, or at the start of the output if no entry did.
+    #[default]
+    KeepWithPrevious,
+    /// Leave them out and only count them.
+    Skip,
+}
+
+/// Options for `merge_with`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeOptions {
+    /// Prefix each message with `[<file stem>] ` of the log it came from.
+    pub tag_source: bool,
+    pub malformed: MalformedLines,
+}
+
+/// Counts from `merge`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    pub entries: u64,
+    pub malformed_kept: u64,
+    pub malformed_skipped: u64,
+}
+
+/// One input and the entry it will contribute next.
+struct Cursor {
+    records: Entries,
+    tag: String,
+    next: Option<LogEntry>,
+}
+
+impl Cursor {
+    /// Moves to the following entry and returns the malformed lines that
+    /// were skipped to get there.
+    fn advance(&mut self) -> Result<Vec<String>, Box<dyn Error>> {
+        let mut skipped = Vec::new();
+        self.next = None;
+
+        for record in self.records.by_ref() {
+            match record? {
+                Record::Entry(entry) => {
+                    self.next = Some(entry);
+                    break;
+                }
+                Record::Malformed { raw, .. } => skipped.push(raw),
+            }
+        }
+
+        Ok(skipped)
+    }
+}
+
+/// Merges the logs in `inputs` into `output` in timestamp order, using
+/// the default `MergeOptions`.
+pub fn merge(inputs: &[&str], output: &str) -> Result<MergeReport, Box<dyn Error>> {
+    merge_with(inputs, output, MergeOptions::default())
+}
+
+/// Merges the logs in `inputs` into `output` in timestamp order. Entries
+/// with equal timestamps keep the order of `inputs`, and each input keeps
+/// its own order. Only one entry per input is held in memory at a time.
+///
+/// `output` goes through the same validation as `errlog` and is appended
+/// to; it may not be one of the inputs. Codes are re-padded to
+/// `DEFAULT_CODE_WIDTH`.
+pub fn merge_with(inputs: &[&str], output: &str, options: MergeOptions) -> Result<MergeReport, Box<dyn Error>> {
+    let output_path = crate::create_path_from_str(output)?;
+    for input in inputs {
+        if crate::is_same_file(input, output)? {
+            return Err(Box::new(crate::error::SamePathError {message: format!("merge output must not be an input: {}", input)}));
+        }
+    }
+
+    let mut cursors = Vec::with_capacity(inputs.len());
+    let mut leading = Vec::new();
+    for input in inputs {
+        let tag = Path::new(input)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mut cursor = Cursor { records: entries(input)?, tag, next: None };
+        leading.extend(cursor.advance()?);
+        cursors.push(cursor);
+    }
+
+    crate::check_or_make_directory(&output_path)?;
+    crate::check_or_make_log(&output_path)?;
+    let file = fs::OpenOptions::new().append(true).open(&output_path)?;
+    let mut out = BufWriter::new(file);
+    let mut report = MergeReport::default();
+
+    let mut write_malformed = |out: &mut BufWriter<fs::File>, lines: Vec<String>| -> Result<(), Box<dyn Error>> {
+        for line in lines {
+            if options.malformed == MalformedLines::Skip {
+                report.malformed_skipped += 1;
+                continue;
+            }
+            report.malformed_kept += 1;
+            out.write_all(format!("\n{}\n", line).as_bytes())?;
+        }
+        Ok(())
+    };
+
+    write_malformed(&mut out, leading)?;
+
+    let mut heap = BinaryHeap::new();
+    for (index, cursor) in cursors.iter().enumerate() {
+        if let Some(entry) = &cursor.next {
+            heap.push(Reverse((entry.timestamp, index)));
+        }
+    }
+
+    while let Some(Reverse((_, index))) = heap.pop() {
+        let cursor = &mut cursors[index];
+        let Some(mut entry) = cursor.next.take() else {
+            continue;
+        };
+        let trailing = cursor.advance()?;
+        if options.tag_source {
+            entry.message = format!("[{}] {}", cursor.tag, entry.message);
+        }
+
+        // formatted up front so the record reaches `out` in one piece
+        out.write_all(format!("\n{}\n", entry).as_bytes())?;
+        report.entries += 1;
+        write_malformed(&mut out, trailing)?;
+
+        if let Some(next) = &cursor.next {
+            heap.push(Reverse((next.timestamp, index)));
+        }
+    }
+
+    out.flush()?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempLogDir;
+
+    fn lines(path: &str) -> Vec<String> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn merge_interleaved_logs() {
+        let dir = TempLogDir::new();
+        let (a, b, c) = (&dir.file("api.log"), &dir.file("db.log"), &dir.file("web.log"));
+        let output = &dir.file("merged.log");
+        fs::write(a, "\n100 - a1\n\n300 - a2\ntorn a\n\n300 - a3\n").unwrap();
+        fs::write(b, "junk before b\n200 - b1\n300 - b2\n").unwrap();
+        fs::write(c, "50 - c1\n300 - [E0007] - c2\n400 - c3\n").unwrap();
+
+        let report = merge_with(&[a, b, c], output, MergeOptions { tag_source: true, ..MergeOptions::default() }).unwrap();
+        assert_eq!(MergeReport { entries: 8, malformed_kept: 2, malformed_skipped: 0 }, report);
+        assert_eq!(
+            vec![
+                "junk before b",
+                "50 - [web] c1",
+                "100 - [api] a1",
+                "200 - [db] b1",
+                "300 - [api] a2",
+                "torn a",
+                "300 - [api] a3",
+                "300 - [db] b2",
+                "300 - [E0007] - [web] c2",
+                "400 - [web] c3",
+            ],
+            lines(output)
+        );
+
+        fs::remove_file(output).unwrap();
+        let report = merge_with(&[a, b, c], output, MergeOptions { malformed: MalformedLines::Skip, ..MergeOptions::default() }).unwrap();
+        assert_eq!(MergeReport { entries: 8, malformed_kept: 0, malformed_skipped: 2 }, report);
+        assert_eq!(
+            vec!["50 - c1", "100 - a1", "200 - b1", "300 - a2", "300 - a3", "300 - b2", "300 - [E0007] - c2", "400 - c3"],
+            lines(output)
+        );
+
+        if merge(&[a, output], output).is_ok() {
+            panic!("Merging a log into itself should have failed.");
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Reading only what was appended since the last look.
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::reader::{continuation, entries, parse_line, LogEntry, Record};
+
+/// Result of `entries_from_offset`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Poll {
+    /// Entries found after the starting offset.
+    pub entries: Vec<LogEntry>,
+    /// Where the next call should resume. Always just past a newline, so a
+    /// half-written last line is picked up once it's complete.
+    pub offset: u64,
+    /// The file was shorter than the given offset, meaning it was
+    /// truncated or rotated, so reading restarted from the beginning.
+    pub restarted: bool,
+}
+
+/// Reads the entries that start at or after `byte_offset` in the log at
+/// `path`. Pass `0` the first time and the returned `offset` after that.
+pub fn entries_from_offset(path: &str, byte_offset: u64) -> Result<Poll, Box<dyn Error>> {
+    let mut file = fs::File::open(path)?;
+    let restarted = file.metadata()?.len() < byte_offset;
+    let mut offset = if restarted { 0 } else { byte_offset };
+    file.seek(SeekFrom::Start(offset))?;
+
+    let mut reader = BufReader::new(file);
+    let mut found = Vec::new();
+    let mut buf = Vec::new();
+    // an entry line and the continuation lines read after it so far
+    let mut current: Option<String> = None;
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 || !buf.ends_with(b"\n") {
+            break;
+        }
+        offset += read as u64;
+
+        let text = String::from_utf8_lossy(&buf);
+        let text = text.trim_end_matches(['\n', '\r']);
+        if let (Some(current), Some(_)) = (&mut current, continuation(text)) {
+            current.push('\n');
+            current.push_str(text);
+            continue;
+        }
+
+        found.extend(current.take().and_then(|entry| parse_line(&entry)));
+        if parse_line(text).is_some() {
+            current = Some(text.to_owned());
+        }
+    }
+    found.extend(current.and_then(|entry| parse_line(&entry)));
+
+    Ok(Poll { entries: found, offset, restarted })
+}
+
+/// Entries in the log at `path` stamped at or after `since`. Timestamps
+/// have whole-second precision, so entries from the same second as `since`
+/// are included; use `entries_from_offset` to never see an entry twice.
+pub fn entries_since(path: &str, since: SystemTime) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+    let since = since.duration_since(SystemTime::UNIX_EPOCH)?.as_secs();
+    let mut found = Vec::new();
+
+    for record in entries(path)? {
+        if let Record::Entry(entry) = record? {
+            if entry.timestamp >= since {
+                found.push(entry);
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// How often `Follow` checks the file for growth unless told otherwise.
+pub const DEFAULT_FOLLOW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Ends a `Follow` from another thread, see `Follow::stop_handle`.
+#[derive(Debug, Clone)]
+pub struct StopHandle(Arc<AtomicBool>);
+
+impl StopHandle {
+    /// The follower returns `None` the next time it would wait for data.
+    pub fn stop(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Blocking iterator over a log's entries as they're appended, like
+/// `tail -f`. Created by `follow`.
+#[derive(Debug)]
+pub struct Follow {
+    path: String,
+    offset: u64,
+    pending: VecDeque<LogEntry>,
+    interval: Duration,
+    timeout: Option<Duration>,
+    last_entry: Instant,
+    stopped: Arc<AtomicBool>,
+}
+
+/// Follows the log at `path`: yields the entries already there, then
+/// waits for new ones. Growth is detected by polling the file's length, and
+/// a file that got shorter is read again from the start, which covers
+/// truncation and rotation to a fresh file. Iteration only ends through
+/// `Follow::timeout` or a `StopHandle`.
+pub fn follow(path: &str) -> Result<Follow, Box<dyn Error>> {
+    fs::metadata(path)?;
+
+    Ok(Follow {
+        path: path.to_owned(),
+        offset: 0,
+        pending: VecDeque::new(),
+        interval: DEFAULT_FOLLOW_INTERVAL,
+        timeout: None,
+        last_entry: Instant::now(),
+        stopped: Arc::new(AtomicBool::new(false)),
+    })
+}
+
+impl Follow {
+    /// Checks the file every `interval` while waiting, instead of every
+    /// `DEFAULT_FOLLOW_INTERVAL`.
+    pub fn interval(mut self, interval: Duration) -> Follow {
+        self.interval = interval;
+        self
+    }
+
+    /// Ends iteration after `timeout` passes without a new entry.
+    pub fn timeout(mut self, timeout: Duration) -> Follow {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        StopHandle(Arc::clone(&self.stopped))
+    }
+
+    fn poll(&mut self) -> Result<(), Box<dyn Error>> {
+        let len = fs::metadata(&self.path)?.len();
+        if len == self.offset {
+            return Ok(());
+        }
+
+        let poll = entries_from_offset(&self.path, self.offset)?;
+        self.offset = poll.offset;
+        self.pending.extend(poll.entries);
+        Ok(())
+    }
+}
+
+impl Iterator for Follow {
+    type Item = io::Result<LogEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(entry) = self.pending.pop_front() {
+                self.last_entry = Instant::now();
+                return Some(Ok(entry));
+            }
+            if self.stopped.load(Ordering::Relaxed) {
+                return None;
+            }
+
+            if let Err(e) = self.poll() {
+                return Some(Err(io::Error::other(e.to_string())));
+            }
+            if !self.pending.is_empty() {
+                continue;
+            }
+
+            if self.timeout.is_some_and(|timeout| self.last_entry.elapsed() >= timeout) {
+                return None;
+            }
+            thread::sleep(self.interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempLogDir;
+    use std::io::Write;
+    use std::time::Duration;
+
+    fn messages(poll: &Poll) -> Vec<&str> {
+        poll.entries.iter().map(|e| e.message.as_str()).collect()
+    }
+
+    #[test]
+    fn poll_loop_with_truncation() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("poll-offset-test.log");
+        fs::write(path, "\n100 - one\n\t| continued\n\n200 - two\n").unwrap();
+
+        let first = entries_from_offset(path, 0).unwrap();
+        assert_eq!(vec!["one\ncontinued", "two"], messages(&first));
+        assert_eq!(fs::metadata(path).unwrap().len(), first.offset);
+        assert!(!first.restarted);
+
+        // a torn line isn't consumed until its newline arrives
+        let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(b"\n300 - three\n\n400 - fo").unwrap();
+        let second = entries_from_offset(path, first.offset).unwrap();
+        assert_eq!(vec!["three"], messages(&second));
+
+        file.write_all(b"ur\n").unwrap();
+        let third = entries_from_offset(path, second.offset).unwrap();
+        assert_eq!(vec!["four"], messages(&third));
+        assert!(entries_from_offset(path, third.offset).unwrap().entries.is_empty());
+
+        fs::write(path, "500 - after truncate\n").unwrap();
+        let fourth = entries_from_offset(path, third.offset).unwrap();
+        assert!(fourth.restarted);
+        assert_eq!(vec!["after truncate"], messages(&fourth));
+    }
+
+    #[test]
+    fn follow_through_truncation() {
+        let dir = TempLogDir::new();
+        let path = dir.file("poll-follow-test.log");
+        fs::write(&path, "\n1 - existing\n").unwrap();
+
+        let follower = follow(&path).unwrap().interval(Duration::from_millis(5)).timeout(Duration::from_millis(750));
+        let stop = follower.stop_handle();
+
+        let writing = path.clone();
+        let writer = std::thread::spawn(move || {
+            for i in 0..5 {
+                std::thread::sleep(Duration::from_millis(15));
+                crate::errlog(&writing, format!("before truncate {}", i)).unwrap();
+            }
+            std::thread::sleep(Duration::from_millis(150));
+            fs::write(&writing, "").unwrap();
+            for i in 0..2 {
+                std::thread::sleep(Duration::from_millis(15));
+                crate::errlog(&writing, format!("after {}", i)).unwrap();
+            }
+        });
+
+        let mut seen = Vec::new();
+        for entry in follower {
+            seen.push(entry.unwrap().message);
+            if seen.len() == 8 {
+                stop.stop();
+            }
+        }
+        writer.join().unwrap();
+
+        assert_eq!(
+            vec![
+                "existing",
+                "before truncate 0",
+                "before truncate 1",
+                "before truncate 2",
+                "before truncate 3",
+                "before truncate 4",
+                "after 0",
+                "after 1",
+            ],
+            seen
+        );
+    }
+
+    #[test]
+    fn follow_times_out_when_idle() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("poll-follow-idle-test.log");
+        fs::write(path, "1 - only\n").unwrap();
+
+        let started = Instant::now();
+        let seen: Vec<LogEntry> = follow(path)
+            .unwrap()
+            .interval(Duration::from_millis(5))
+            .timeout(Duration::from_millis(50))
+            .map(|entry| entry.unwrap())
+            .collect();
+        assert_eq!(1, seen.len());
+        assert!(started.elapsed() >= Duration::from_millis(50));
+    }
+
+    #[test]
+    fn entries_since_checkpoint() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("poll-since-test.log");
+        fs::write(path, "\n100 - old\n\n200 - edge\n\n300 - new\n").unwrap();
+
+        let since = SystemTime::UNIX_EPOCH + Duration::from_secs(200);
+        let found: Vec<String> = entries_since(path, since).unwrap().into_iter().map(|e| e.message).collect();
+        assert_eq!(vec!["edge", "new"], found);
+    }
+}
@@ -0,0 +1,10 @@
+//! The commonly used parts of the crate in one import:
+//!
+//! ```
+//! use errlog::prelude::*;
+//! ```
+
+pub use crate::error::{BadExtensionError, ConfigError, FieldNameError, FileNameError, IoContext, NotWritableError, ParseEntryError, SamePathError, SchemaVersionError, SinkError, SocketNotFoundError, TimeoutError, into_io_error};
+pub use crate::logger::Logger;
+pub use crate::reader::{entries, entries_json, read_entries, read_entries_json, LogEntry, Record};
+pub use crate::{errlog, errlog_coded};
@@ -0,0 +1,970 @@
+//! Reading a log back into entries. Lines are expected in the shape
+//! written by this crate, `<timestamp> - <message>`, optionally with an
+//! error code in between: `<timestamp> - [E0042] - <message>`.
+//!
+//! A message with line breaks is written as the entry line followed by one
+//! continuation line per break. Continuation lines start with a tab and
+//! `| ` for a `\n` break, or `|r ` for a `\r\n` one, and come straight
+//! after the line they continue:
+//!
+//! ```text
+//! "1690999212 - request failed\n\t| caused by: timeout"
+//! ```
+//!
+//! The full layout is versioned as `FORMAT_VERSION`.
+//!
+//! None of the parsers or readers here panic, whatever the input: bad text
+//! is an `Err` or a `Record::Malformed`, never a crash.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use std::str::FromStr;
+
+use crate::error::ParseEntryError;
+use crate::logger::is_marker;
+
+/// Version of the plain on-disk layout that the writers produce and the
+/// readers expect. It only goes up when a line would be written
+/// differently or read back with a different meaning; the files under
+/// `tests/golden` pin each version byte for byte.
+///
+/// Version 1, with `\n` for the terminator:
+///
+/// ```text
+/// file         = *( record | blank )
+/// record       = [ LF ] ( entry | separator | heartbeat ) LF
+/// entry        = seconds [ "." 3DIGIT ] " - " [ code " - " ] text *continued [ checksum ]
+/// seconds      = 1*DIGIT                 ; since UNIX_EPOCH, UTC
+/// code         = "[E" 1*DIGIT "]"        ; zero-padded to DEFAULT_CODE_WIDTH
+/// continued    = LF TAB "|" [ "r" ] " " text
+///                                        ; "|" for a \n break, "|r" for \r\n
+/// checksum     = " #" 8HEXDIG            ; lowercase
+/// separator    = "===== run started " YYYY-MM-DD " " HH:MM:SS " (format " 1*DIGIT ") ====="
+/// heartbeat    = "===== heartbeat " YYYY-MM-DD " " HH:MM:SS " entries=" 1*DIGIT " uptime=" 1*DIGIT "s ====="
+/// text         = *( any character but LF )
+/// ```
+///
+/// A `Logger` starts every record with `LF`, while `compact_log` writes
+/// records without it. Fields from `Logger::log_fields` are
+/// part of `text`, as ` key=value` pairs quoted the logfmt way. Separators
+/// written before the version existed have no `(format ..)` and are
+/// version 1. With another terminator, records end with it instead of `LF`
+/// and messages keep their line breaks as they are.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// A single parsed line of an error log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEntry {
+    /// Seconds since `UNIX_EPOCH` at the time of writing.
+    pub timestamp: u64,
+    /// Milliseconds into that second, only written by a logger with
+    /// `Logger::with_millis_on_collision`, as `<seconds>.<millis>`.
+    pub millis: Option<u16>,
+    /// The numeric code if the entry was written with `errlog_coded`.
+    pub code: Option<u32>,
+    pub message: String,
+    /// The ` #xxxxxxxx` suffix if the entry was written by a logger with
+    /// `Logger::with_checksums` and read with `Entries::with_checksums`,
+    /// which takes it out of `message`. Other readers leave it in the
+    /// message, since ordinary text can end the same way.
+    pub checksum: Option<u32>,
+    /// Keys other than the crate's own found on a JSON object read with
+    /// `from_json` or `entries_json`, so entries from a newer writer keep
+    /// them. Numbers are kept as their digits. Always empty for entries
+    /// read from a plain log, and not part of how an entry is displayed.
+    pub fields: BTreeMap<String, String>,
+}
+
+impl LogEntry {
+    /// The entry as a JSON object, in the same shape `export_json` writes:
+    /// `{"v":1,"timestamp":..,"code":..,"message":".."}` with `code` left out
+    /// when there isn't one.
+    pub fn to_json(&self) -> String {
+        crate::json::entry_to_json(self)
+    }
+
+    /// The entry as one logfmt line, in the same shape `export_logfmt`
+    /// writes: `ts=2024-03-07T14:09:03Z code=42 msg="disk full"`, with
+    /// `code` left out when there isn't one.
+    pub fn to_logfmt(&self) -> String {
+        crate::logfmt::entry_to_logfmt(self, &[])
+    }
+
+    /// `to_logfmt` with `fields` added after `msg`, in the given order.
+    pub fn to_logfmt_with(&self, fields: &[(&str, &str)]) -> String {
+        crate::logfmt::entry_to_logfmt(self, fields)
+    }
+
+    /// The entry as an RFC 5424 syslog line, in the same shape
+    /// `export_syslog` writes, see `SyslogOptions`.
+    pub fn to_syslog(&self, options: &crate::SyslogOptions) -> String {
+        crate::syslog::entry_to_syslog(self, options)
+    }
+
+    /// Reads back one object from `to_json` or `export_json`, of any schema
+    /// version up to `JSON_SCHEMA_VERSION`. A newer version fails with
+    /// `SchemaVersionError` rather than being guessed at. Never panics,
+    /// whatever `text` holds.
+    pub fn from_json(text: &str) -> Result<LogEntry, Box<dyn Error>> {
+        crate::json::entry_from_json(text)
+    }
+}
+
+/// Renders the entry exactly as the writer lays out a line, without the
+/// terminator, with line breaks in the message turned into continuation
+/// lines. Codes are padded to `DEFAULT_CODE_WIDTH`. A message that
+/// itself starts with something like `[E12] - ` will read back as coded.
+/// A checksum is written after the message, and only
+/// `Entries::with_checksums` reads it back as one.
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.timestamp)?;
+        if let Some(millis) = self.millis {
+            write!(f, ".{:03}", millis)?;
+        }
+        match self.code {
+            Some(code) => write!(f, " - [E{:0width$}] - {}", code, encode_message(&self.message), width = crate::DEFAULT_CODE_WIDTH)?,
+            None => write!(f, " - {}", encode_message(&self.message))?,
+        }
+        match self.checksum {
+            Some(checksum) => write!(f, " #{:08x}", checksum),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Parses one entry in the plain format, including any continuation lines,
+/// without its terminator. Never panics: anything else is a
+/// `ParseEntryError`.
+impl FromStr for LogEntry {
+    type Err = ParseEntryError;
+
+    fn from_str(line: &str) -> Result<LogEntry, ParseEntryError> {
+        parse_line(line).ok_or_else(|| ParseEntryError {message: format!("not a log entry: {:?}", line)})
+    }
+}
+
+/// One non-blank line of a log, or an entry with its continuation lines,
+/// as yielded by `entries`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+    Entry(LogEntry),
+    /// A line that couldn't be parsed, with its 1-based line number. This
+    /// includes continuation lines with no entry right before them.
+    Malformed { line: usize, raw: String },
+}
+
+/// Streaming iterator over the records of a log, see `entries`.
+pub struct Entries {
+    reader: BufReader<fs::File>,
+    terminator: Vec<u8>,
+    line: usize,
+    /// Bytes read so far, counted from where reading started.
+    offset: u64,
+    /// Where the line of the last record returned by `next_record` starts.
+    start: u64,
+    peeked: Option<Record>,
+    /// A line read while looking for continuation lines, with its number
+    /// and offset.
+    pending: Option<(usize, u64, String)>,
+    skip: usize,
+    skipping_trailing: bool,
+    /// Whether a ` #xxxxxxxx` suffix is read as a checksum.
+    checksums: bool,
+}
+
+impl Entries {
+    /// Reads `file` from its current position, which is byte `offset` of
+    /// the log.
+    fn new(file: fs::File, terminator: &[u8], offset: u64) -> Entries {
+        Entries {
+            reader: BufReader::new(file),
+            terminator: terminator.to_vec(),
+            line: 0,
+            offset,
+            start: offset,
+            peeked: None,
+            pending: None,
+            skip: 0,
+            skipping_trailing: false,
+            checksums: false,
+        }
+    }
+
+    /// Reads up to and including the next terminator into `buf`.
+    fn read_record(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        let last = *self.terminator.last().expect("terminator must not be empty");
+        let mut read = 0;
+        loop {
+            let n = self.reader.read_until(last, buf)?;
+            read += n;
+            if n == 0 || buf.ends_with(&self.terminator) {
+                return Ok(read);
+            }
+        }
+    }
+}
+
+impl Entries {
+    /// Skips the first `n` entries, along with any malformed lines that
+    /// follow them. Lines are never counted, so legacy blank lines and
+    /// stray fragments don't shift the page.
+    pub fn skip_entries(mut self, n: usize) -> Entries {
+        self.skip += n;
+        self
+    }
+
+    /// Reads a trailing ` #` and 8 lowercase hex digits as the entry's
+    /// `checksum` rather than as part of its message, for logs written
+    /// `Logger::with_checksums`. Off by default, so a message like
+    /// `pushed commit #deadbeef` in an ordinary log reads back whole.
+    pub fn with_checksums(mut self) -> Entries {
+        self.checksums = true;
+        self
+    }
+
+    /// Stops after `n` entries. Malformed lines directly after the last
+    /// one are still yielded, so consecutive pages never split an entry
+    /// from its trailing lines.
+    pub fn take_entries(self, n: usize) -> TakeEntries {
+        TakeEntries { entries: self, remaining: n, taken_any: false }
+    }
+
+    /// The next line without its terminator, its line number and the
+    /// offset it starts at.
+    fn next_line(&mut self) -> Option<io::Result<(usize, u64, String)>> {
+        if let Some(line) = self.pending.take() {
+            return Some(Ok(line));
+        }
+
+        let start = self.offset;
+        let mut buf = Vec::new();
+        match self.read_record(&mut buf) {
+            Ok(0) => return None,
+            Ok(read) => {
+                self.line += 1;
+                self.offset += read as u64;
+            }
+            Err(e) => return Some(Err(e)),
+        }
+
+        if buf.ends_with(&self.terminator) {
+            buf.truncate(buf.len() - self.terminator.len());
+        }
+        if self.terminator == b"\n" && buf.ends_with(b"\r") {
+            buf.pop();
+        }
+
+        Some(Ok((self.line, start, String::from_utf8_lossy(&buf).into_owned())))
+    }
+
+    fn next_record(&mut self) -> Option<io::Result<Record>> {
+        if let Some(record) = self.peeked.take() {
+            return Some(Ok(record));
+        }
+
+        loop {
+            let (line, start, mut text) = match self.next_line()? {
+                Ok(line) => line,
+                Err(e) => return Some(Err(e)),
+            };
+            if text.is_empty() || is_marker(&text) {
+                continue;
+            }
+            self.start = start;
+            if self.terminator != b"\n" {
+                return Some(Ok(match parse_entry(&text, false, self.checksums) {
+                    Some(entry) => Record::Entry(entry),
+                    None => Record::Malformed { line, raw: text },
+                }));
+            }
+            if continuation(&text).is_some() || parse_line(&text).is_none() {
+                return Some(Ok(Record::Malformed { line, raw: text }));
+            }
+
+            loop {
+                match self.next_line() {
+                    Some(Ok((_, _, next))) if continuation(&next).is_some() => {
+                        text.push('\n');
+                        text.push_str(&next);
+                    }
+                    Some(Ok(next)) => {
+                        self.pending = Some(next);
+                        break;
+                    }
+                    Some(Err(e)) => return Some(Err(e)),
+                    None => break,
+                }
+            }
+
+            return Some(Ok(match parse_entry(&text, true, self.checksums) {
+                Some(entry) => Record::Entry(entry),
+                None => Record::Malformed { line, raw: text },
+            }));
+        }
+    }
+}
+
+impl Iterator for Entries {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let record = match self.next_record()? {
+                Ok(record) => record,
+                Err(e) => return Some(Err(e)),
+            };
+
+            match record {
+                Record::Entry(_) if self.skip > 0 => {
+                    self.skip -= 1;
+                    self.skipping_trailing = self.skip == 0;
+                }
+                Record::Malformed { .. } if self.skip > 0 || self.skipping_trailing => {}
+                record => {
+                    self.skipping_trailing = false;
+                    return Some(Ok(record));
+                }
+            }
+        }
+    }
+}
+
+/// Iterator returned by `Entries::take_entries`.
+pub struct TakeEntries {
+    entries: Entries,
+    remaining: usize,
+    taken_any: bool,
+}
+
+impl Iterator for TakeEntries {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 && !self.taken_any {
+            return None;
+        }
+
+        match self.entries.next()? {
+            Ok(Record::Entry(entry)) => {
+                if self.remaining == 0 {
+                    self.entries.peeked = Some(Record::Entry(entry));
+                    self.taken_any = false;
+                    return None;
+                }
+                self.remaining -= 1;
+                self.taken_any = true;
+                Some(Ok(Record::Entry(entry)))
+            }
+            other => Some(other),
+        }
+    }
+}
+
+/// Opens the log at `path` for reading one line at a time. Blank lines, run
+/// separators and heartbeats are skipped, anything else that isn't an entry comes back as
+/// `Record::Malformed`. No file contents make it panic, invalid UTF-8
+/// included, which is read lossily.
+pub fn entries(path: &str) -> Result<Entries, Box<dyn Error>> {
+    entries_with_terminator(path, b"\n")
+}
+
+/// Same as `entries` for a log whose records end with `terminator` rather
+/// than a newline, as written by `Logger::with_terminator`.
+pub fn entries_with_terminator(path: &str, terminator: &[u8]) -> Result<Entries, Box<dyn Error>> {
+    if terminator.is_empty() {
+        return Err(Box::new(io::Error::new(io::ErrorKind::InvalidInput, "terminator must not be empty")));
+    }
+
+    Ok(Entries::new(fs::File::open(path)?, terminator, 0))
+}
+
+/// Streaming iterator over the records of a JSON Lines log, see
+/// `entries_json`.
+pub struct JsonEntries {
+    lines: io::Lines<BufReader<fs::File>>,
+    line: usize,
+}
+
+impl Iterator for JsonEntries {
+    type Item = io::Result<Record>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let text = match self.lines.next()? {
+                Ok(text) => text,
+                Err(e) => return Some(Err(e)),
+            };
+            self.line += 1;
+            if text.trim().is_empty() {
+                continue;
+            }
+
+            return Some(
+                crate::json::record_from_json(&text, self.line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            );
+        }
+    }
+}
+
+/// Opens a log written by `export_json` for reading one object at a time.
+/// Blank lines are skipped. A `{"malformed": ..}` object comes back as
+/// `Record::Malformed` with the raw line it holds, and so does any line
+/// that isn't a JSON entry object, as it is. An object with a schema
+/// version newer than `JSON_SCHEMA_VERSION` is an `InvalidData` error
+/// wrapping a `SchemaVersionError`, since its entries can't be trusted to
+/// read the same. No file contents make it panic.
+pub fn entries_json(path: &str) -> Result<JsonEntries, Box<dyn Error>> {
+    let file = fs::File::open(path)?;
+    Ok(JsonEntries { lines: BufReader::new(file).lines(), line: 0 })
+}
+
+/// Reads every entry from a log written by `export_json`, see
+/// `entries_json`. Malformed records are skipped.
+pub fn read_entries_json(path: &str) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+    let mut found = Vec::new();
+    for record in entries_json(path)? {
+        if let Record::Entry(entry) = record? {
+            found.push(entry);
+        }
+    }
+
+    Ok(found)
+}
+
+/// Reads every entry from the log at `path`. Blank lines and lines that
+/// don't look like an entry are skipped.
+pub fn read_entries(path: &str) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+    let mut found = Vec::new();
+    for record in entries(path)? {
+        if let Record::Entry(entry) = record? {
+            found.push(entry);
+        }
+    }
+
+    Ok(found)
+}
+
+/// Every entry in the log at `path` with the byte offset its first line
+/// starts at, for `read_entry_at`. Lines that aren't entries are skipped,
+/// as by `read_entries`.
+pub fn index_entries(path: &str) -> Result<Vec<(u64, LogEntry)>, Box<dyn Error>> {
+    let mut records = entries(path)?;
+    let mut found = Vec::new();
+    while let Some(record) = records.next_record() {
+        if let Record::Entry(entry) = record? {
+            found.push((records.start, entry));
+        }
+    }
+
+    Ok(found)
+}
+
+/// The entry whose first line starts at byte `offset` of the log at
+/// `path`, as found by `index_entries`, along with its continuation lines.
+/// Fails with `ParseEntryError` when no entry starts there, including when
+/// `offset` is in the middle of a line.
+pub fn read_entry_at(path: &str, offset: u64) -> Result<LogEntry, Box<dyn Error>> {
+    let no_entry = || Box::new(ParseEntryError { message: format!("no entry starts at byte {} of {}", offset, path) });
+    let mut file = fs::File::open(path)?;
+    if offset > 0 {
+        let mut before = [0];
+        file.seek(SeekFrom::Start(offset - 1))?;
+        if file.read(&mut before)? == 0 || before != *b"\n" {
+            return Err(no_entry());
+        }
+    }
+
+    let mut records = Entries::new(file, b"\n", offset);
+    match records.next_record().transpose()? {
+        Some(Record::Entry(entry)) if records.start == offset => Ok(entry),
+        _ => Err(no_entry()),
+    }
+}
+
+/// The first `n` entries of the log at `path`. Reading stops as soon as
+/// the `n`th entry is found.
+pub fn head(path: &str, n: usize) -> Result<Vec<LogEntry>, Box<dyn Error>> {
+    let mut found = Vec::with_capacity(n);
+    if n == 0 {
+        return Ok(found);
+    }
+
+    for record in entries(path)? {
+        if let Record::Entry(entry) = record? {
+            found.push(entry);
+            if found.len() == n {
+                break;
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+pub(crate) fn parse_line(line: &str) -> Option<LogEntry> {
+    parse_entry(line, true, false)
+}
+
+/// `parse_line` for a line written with a checksum, see
+/// `Entries::with_checksums`.
+pub(crate) fn parse_checksummed_line(line: &str) -> Option<LogEntry> {
+    parse_entry(line, true, true)
+}
+
+/// Parses an entry, turning continuation lines back into line breaks when
+/// `continued` is set. Records with other terminators hold raw line breaks.
+fn parse_entry(line: &str, continued: bool, checksums: bool) -> Option<LogEntry> {
+    let (line, checksum) = if checksums { split_checksum(line) } else { (line, None) };
+    let (timestamp, rest) = line.split_once(" - ")?;
+    let (timestamp, millis) = match timestamp.split_once('.') {
+        Some((secs, millis)) if millis.len() == 3 && millis.bytes().all(|b| b.is_ascii_digit()) => (secs, Some(millis.parse().ok()?)),
+        Some(_) => return None,
+        None => (timestamp, None),
+    };
+    let timestamp = timestamp.parse::<u64>().ok()?;
+
+    let (code, message) = match parse_code(rest) {
+        Some((code, message)) => (Some(code), message),
+        None => (None, rest),
+    };
+
+    Some(LogEntry {
+        timestamp,
+        millis,
+        code,
+        message: if continued { decode_message(message)? } else { message.to_owned() },
+        checksum,
+        fields: BTreeMap::new(),
+    })
+}
+
+/// Splits a continuation line into the line break it stands for and the
+/// text after its prefix.
+pub(crate) fn continuation(line: &str) -> Option<(&'static str, &str)> {
+    match line.strip_prefix("\t| ") {
+        Some(rest) => Some(("\n", rest)),
+        None => line.strip_prefix("\t|r ").map(|rest| ("\r\n", rest)),
+    }
+}
+
+/// Writes each line break in `message` as a newline followed by a
+/// continuation prefix.
+pub(crate) fn encode_message(message: &str) -> Cow<'_, str> {
+    if !message.contains('\n') {
+        return Cow::Borrowed(message);
+    }
+
+    let mut encoded = String::with_capacity(message.len() + 8);
+    let mut lines = message.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        if lines.peek().is_none() {
+            encoded.push_str(line);
+            break;
+        }
+        match line.strip_suffix('\r') {
+            Some(line) => {
+                encoded.push_str(line);
+                encoded.push_str("\n\t|r ");
+            }
+            None => {
+                encoded.push_str(line);
+                encoded.push_str("\n\t| ");
+            }
+        }
+    }
+
+    Cow::Owned(encoded)
+}
+
+/// Reverses `encode_message`. Fails if a line after the first isn't a
+/// continuation line.
+fn decode_message(message: &str) -> Option<String> {
+    let mut lines = message.split('\n');
+    let mut decoded = String::from(lines.next()?);
+    for line in lines {
+        let (newline, rest) = continuation(line)?;
+        decoded.push_str(newline);
+        decoded.push_str(rest);
+    }
+
+    Some(decoded)
+}
+
+/// Separates a trailing ` #xxxxxxxx` checksum (lowercase hex) from the
+/// content it covers.
+pub(crate) fn split_checksum(line: &str) -> (&str, Option<u32>) {
+    let Some(split) = line.len().checked_sub(10) else {
+        return (line, None);
+    };
+    let Some((content, suffix)) = line.split_at_checked(split) else {
+        return (line, None);
+    };
+
+    match suffix.strip_prefix(" #") {
+        Some(hex) if hex.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')) => {
+            (content, u32::from_str_radix(hex, 16).ok())
+        }
+        _ => (line, None),
+    }
+}
+
+fn parse_code(text: &str) -> Option<(u32, &str)> {
+    let text = text.strip_prefix("[E")?;
+    let (digits, message) = text.split_once("] - ")?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    Some((digits.parse().ok()?, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempLogDir;
+
+    #[test]
+    fn parse_plain_line() {
+        let entry = parse_line("1690999212 - error log was successful").unwrap();
+        assert_eq!(1690999212, entry.timestamp);
+        assert_eq!(None, entry.code);
+        assert_eq!("error log was successful", entry.message);
+    }
+
+    #[test]
+    fn parse_coded_line() {
+        let entry = parse_line("1690999212 - [E0042] - disk full").unwrap();
+        assert_eq!(Some(42), entry.code);
+        assert_eq!("disk full", entry.message);
+
+        let entry = parse_line("1690999212 - [Ex42] - not a code").unwrap();
+        assert_eq!(None, entry.code);
+        assert_eq!("[Ex42] - not a code", entry.message);
+    }
+
+    #[test]
+    fn parse_checksummed_lines() {
+        let entry = parse_checksummed_line("1690999212 - [E0042] - disk full #0badf00d").unwrap();
+        assert_eq!(Some(42), entry.code);
+        assert_eq!("disk full", entry.message);
+        assert_eq!(Some(0x0badf00d), entry.checksum);
+
+        let entry = parse_checksummed_line("1690999212 - issue #1234").unwrap();
+        assert_eq!(None, entry.checksum);
+        assert_eq!("issue #1234", entry.message);
+
+        let entry = parse_checksummed_line("1690999212 - upper #0BADF00D").unwrap();
+        assert_eq!(None, entry.checksum);
+
+        // without checksums turned on, text that looks like one is kept
+        let entry = parse_line("1690999212 - pushed commit #deadbeef").unwrap();
+        assert_eq!((None, "pushed commit #deadbeef"), (entry.checksum, entry.message.as_str()));
+        let entry: LogEntry = "1690999212 - pushed commit #deadbeef".parse().unwrap();
+        assert_eq!("pushed commit #deadbeef", entry.message);
+    }
+
+    #[test]
+    fn checksums_are_only_read_when_asked_for() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("checksum-opt-in-test.log");
+        fs::write(path, "\n1 - pushed commit #deadbeef\n").unwrap();
+        assert_eq!("pushed commit #deadbeef", read_entries(path).unwrap()[0].message);
+
+        let entry = match entries(path).unwrap().with_checksums().next() {
+            Some(Ok(Record::Entry(entry))) => entry,
+            other => panic!("Expected an entry, got {:?}", other),
+        };
+        assert_eq!(("pushed commit", Some(0xdeadbeef)), (entry.message.as_str(), entry.checksum));
+    }
+
+    #[test]
+    fn parse_millis() {
+        let entry = parse_line("1690999212.045 - [E0042] - close together").unwrap();
+        assert_eq!((1690999212, Some(45), Some(42)), (entry.timestamp, entry.millis, entry.code));
+        assert_eq!(None, parse_line("1690999212 - plain").unwrap().millis);
+
+        for bad in ["1690999212.45 - x", "1690999212.+45 - x", "1690999212.0450 - x", ".045 - x"] {
+            assert!(parse_line(bad).is_none(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn entries_reports_malformed_lines() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("reader-entries-test.log");
+        fs::write(path, "\n1690999200 - first\n\ngarbage\n1690999212 - second\n").unwrap();
+
+        let records: Vec<Record> = entries(path).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(3, records.len());
+        assert_eq!(Record::Malformed { line: 4, raw: String::from("garbage") }, records[1]);
+        assert_eq!(2, read_entries(path).unwrap().len());
+    }
+
+    #[test]
+    fn continuation_lines_round_trip() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("reader-continuation-test.log");
+        let messages = [
+            "first\nsecond",
+            "windows\r\nline\nmixed",
+            "indented\n    four spaces\n\ttab",
+            "literal prefix\n\t| not a break\n\t|r nor this",
+            "trailing newline\n",
+            "\nleading newline",
+            "blank\n\nline inside",
+        ];
+        for message in messages {
+            crate::errlog(path, String::from(message)).unwrap();
+        }
+        crate::errlog_coded(path, 7, String::from("coded\nmulti")).unwrap();
+
+        let found = read_entries(path).unwrap();
+        let found: Vec<&str> = found.iter().map(|entry| entry.message.as_str()).collect();
+        assert_eq!([&messages[..], &["coded\nmulti"]].concat(), found);
+        assert!(!fs::read_to_string(path).unwrap().contains('\r'));
+
+        for message in messages {
+            let mut entry = LogEntry { timestamp: 9, millis: None, code: Some(3), message: String::from(message), checksum: None, fields: BTreeMap::new() };
+            assert_eq!(entry, entry.to_string().parse::<LogEntry>().unwrap());
+            entry.checksum = Some(1);
+            assert_eq!(Some(&entry), parse_checksummed_line(&entry.to_string()).as_ref());
+        }
+    }
+
+    #[test]
+    fn orphaned_continuation_lines_are_malformed() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("reader-orphan-test.log");
+        fs::write(path, "\t| at the start\n\n1 - one\n\t| more one\n\n\t| after a blank\ngarbage\n\t| after garbage\n2 - two\n").unwrap();
+
+        let records: Vec<Record> = entries(path).unwrap().map(|r| r.unwrap()).collect();
+        assert_eq!(
+            vec![
+                Record::Malformed { line: 1, raw: String::from("\t| at the start") },
+                Record::Entry(LogEntry { timestamp: 1, millis: None, code: None, message: String::from("one\nmore one"), checksum: None, fields: BTreeMap::new() }),
+                Record::Malformed { line: 6, raw: String::from("\t| after a blank") },
+                Record::Malformed { line: 7, raw: String::from("garbage") },
+                Record::Malformed { line: 8, raw: String::from("\t| after garbage") },
+                Record::Entry(LogEntry { timestamp: 2, millis: None, code: None, message: String::from("two"), checksum: None, fields: BTreeMap::new() }),
+            ],
+            records
+        );
+        assert!("1 - one\nnot a continuation".parse::<LogEntry>().is_err());
+    }
+
+    #[test]
+    fn pages_tile_the_file() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("reader-pages-test.log");
+        let log = "\n1 - one\n\n2 - two\nsecond line of two\n\n3 - three\n\n4 - four\nmore four\nand more\n\n5 - five\n\n6 - six\n\n7 - seven\n";
+        fs::write(path, log).unwrap();
+
+        let all: Vec<Record> = entries(path).unwrap().map(|r| r.unwrap()).collect();
+        let first: Vec<Record> = entries(path).unwrap().take_entries(3).map(|r| r.unwrap()).collect();
+        let second: Vec<Record> = entries(path).unwrap().skip_entries(3).take_entries(3).map(|r| r.unwrap()).collect();
+        let rest: Vec<Record> = entries(path).unwrap().skip_entries(6).map(|r| r.unwrap()).collect();
+        assert_eq!(4, first.len());
+        assert_eq!(5, second.len());
+        assert_eq!(1, rest.len());
+        assert_eq!(all, [first, second, rest].concat());
+
+        let head: Vec<u64> = head(path, 3).unwrap().iter().map(|e| e.timestamp).collect();
+        assert_eq!(vec![1, 2, 3], head);
+        let page: Vec<u64> = entries(path)
+            .unwrap()
+            .skip_entries(3)
+            .take_entries(3)
+            .filter_map(|r| match r.unwrap() {
+                Record::Entry(entry) => Some(entry.timestamp),
+                Record::Malformed { .. } => None,
+            })
+            .collect();
+        assert_eq!(vec![4, 5, 6], page);
+        assert!(entries(path).unwrap().take_entries(0).next().is_none());
+    }
+
+    #[test]
+    fn display_and_from_str_round_trip() {
+        let messages = [
+            "plain",
+            "with \"quotes\" and 'apostrophes'",
+            "unicode: caf\u{e9} \u{2603} \u{1f980}",
+            "separator - in - the middle",
+            " - leading separator",
+            "trailing separator - ",
+            "back\\slash and \ttab",
+            "",
+        ];
+
+        for message in messages {
+            for code in [None, Some(0), Some(42), Some(123_456)] {
+                let entry = LogEntry { timestamp: 1_690_999_212, millis: None, code, message: String::from(message), checksum: None, fields: BTreeMap::new() };
+                let line = entry.to_string();
+                assert_eq!(entry, line.parse::<LogEntry>().unwrap(), "line: {:?}", line);
+
+                let json = crate::json::tokenizer::decode_object(&entry.to_json());
+                assert_eq!(
+                    (String::from("message"), crate::json::tokenizer::Value::Str(String::from(message))),
+                    *json.last().unwrap()
+                );
+            }
+        }
+
+        assert_eq!("5 - [E0042] - x", LogEntry { timestamp: 5, millis: None, code: Some(42), message: String::from("x"), checksum: None, fields: BTreeMap::new() }.to_string());
+        assert_eq!("5 - x #0000beef", LogEntry { timestamp: 5, millis: None, code: None, message: String::from("x"), checksum: Some(0xbeef), fields: BTreeMap::new() }.to_string());
+        assert!("not an entry".parse::<LogEntry>().is_err());
+    }
+
+    #[test]
+    fn entries_read_back_by_offset() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("index-test.log");
+        let contents = "\n1 - first\n===== run started 2024-03-07 14:09:03 =====\n\nnot an entry\n2 - [E0042] - second\n\t| continued\n3 - third";
+        fs::write(path, contents).unwrap();
+
+        let index = index_entries(path).unwrap();
+        let offsets: Vec<u64> = index.iter().map(|(offset, _)| *offset).collect();
+        let starts = ["1 - first", "2 - [E0042]", "3 - third"].map(|line| contents.find(line).unwrap() as u64);
+        assert_eq!(starts.to_vec(), offsets);
+        assert_eq!(read_entries(path).unwrap(), index.iter().map(|(_, entry)| entry.clone()).collect::<Vec<_>>());
+
+        let second = read_entry_at(path, index[1].0).unwrap();
+        assert_eq!(Some(42), second.code);
+        assert_eq!("second\ncontinued", second.message);
+        assert_eq!(index[2].1, read_entry_at(path, index[2].0).unwrap());
+
+        // the middle of a line, a line that isn't an entry and the end of the file
+        for offset in [index[1].0 + 1, contents.find("not an").unwrap() as u64, contents.len() as u64] {
+            match read_entry_at(path, offset) {
+                Err(e) if e.is::<ParseEntryError>() => {}
+                other => panic!("Expected no entry at {}, got {:?}", offset, other),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_bad_lines() {
+        assert_eq!(None, parse_line(""));
+        assert_eq!(None, parse_line("no timestamp here"));
+        assert_eq!(None, parse_line("abc - message"));
+    }
+
+    /// xorshift64, so the cases are the same on every run.
+    struct Xorshift(u64);
+
+    impl Xorshift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+
+        /// Bytes mostly from the ones the formats give meaning to.
+        fn soup(&mut self, max_len: usize) -> Vec<u8> {
+            const MEANINGFUL: &[u8] = b"0123456789abcdefE -[]#.:,{}\"\\u\t|r\n\r";
+            let len = self.below(max_len + 1);
+            (0..len)
+                .map(|_| match self.below(4) {
+                    0 => self.next() as u8,
+                    _ => MEANINGFUL[self.below(MEANINGFUL.len())],
+                })
+                .collect()
+        }
+
+        /// A line the writer could have produced, in the plain format or as
+        /// JSON, with a few bytes inserted, replaced or removed.
+        fn near_valid(&mut self) -> Vec<u8> {
+            let entry = LogEntry {
+                timestamp: [0, 1_690_999_212, u64::MAX][self.below(3)],
+                millis: [None, Some(0), Some(999)][self.below(3)],
+                code: [None, Some(0), Some(42), Some(u32::MAX)][self.below(4)],
+                message: String::from_utf8_lossy(&self.soup(12)).into_owned(),
+                checksum: [None, Some(0xbeef)][self.below(2)],
+                fields: BTreeMap::new(),
+            };
+            let mut bytes = match self.below(2) {
+                0 => entry.to_string().into_bytes(),
+                _ => entry.to_json().into_bytes(),
+            };
+            for _ in 0..=self.below(3) {
+                let at = self.below(bytes.len() + 1);
+                let byte = self.soup(1).first().copied().unwrap_or(b'"');
+                match self.below(3) {
+                    0 => bytes.insert(at, byte),
+                    1 if at < bytes.len() => bytes[at] = byte,
+                    _ if at < bytes.len() => drop(bytes.remove(at)),
+                    _ => {}
+                }
+            }
+            bytes
+        }
+    }
+
+    #[test]
+    fn arbitrary_input_never_panics() {
+        let mut rng = Xorshift(0x2545_f491_4f6c_dd1d);
+        for case in 0..300_000 {
+            let bytes = if case % 2 == 0 { rng.soup(48) } else { rng.near_valid() };
+            let text = String::from_utf8_lossy(&bytes);
+            let outcome = std::panic::catch_unwind(|| {
+                let _ = text.parse::<LogEntry>();
+                let _ = parse_entry(&text, false, true);
+                let _ = LogEntry::from_json(&text);
+                let _ = crate::create_path_from_str(&text);
+            });
+            if outcome.is_err() {
+                panic!("case {} panicked on {:?}", case, text);
+            }
+
+            let days = rng.next() as i64;
+            let _ = crate::civil_from_days(days);
+            let _ = crate::days_from_civil(days, rng.next() as u32, rng.next() as u32);
+            let _ = crate::date::rfc3339_fixed(rng.next(), Some(rng.next() as u16));
+        }
+
+        // whatever a plain line parses to reads back the same once written
+        for _ in 0..50_000 {
+            let text = String::from_utf8_lossy(&rng.near_valid()).into_owned();
+            if let Ok(entry) = text.parse::<LogEntry>() {
+                assert_eq!(Some(&entry), entry.to_string().parse::<LogEntry>().ok().as_ref(), "from {:?}", text);
+            }
+        }
+
+        let dir = TempLogDir::new();
+        let path = &dir.file("arbitrary-input-test.log");
+        for case in 0..300 {
+            let mut contents = Vec::new();
+            for _ in 0..rng.below(8) {
+                contents.extend(if rng.below(2) == 0 { rng.soup(48) } else { rng.near_valid() });
+                contents.push(b'\n');
+            }
+            fs::write(path, &contents).unwrap();
+            let terminator = rng.soup(2);
+
+            let outcome = std::panic::catch_unwind(|| {
+                let _ = read_entries(path);
+                let _ = read_entries_json(path);
+                let _ = head(path, 2);
+                if let Ok(records) = entries_with_terminator(path, &terminator) {
+                    records.skip_entries(1).take_entries(1).for_each(drop);
+                }
+                let _ = crate::entries_from_offset(path, (case * 7) as u64);
+                let _ = index_entries(path);
+                let _ = read_entry_at(path, [(case * 7) as u64, u64::MAX][case % 2]);
+            });
+            if outcome.is_err() {
+                panic!("file case {} panicked on {:?} with terminator {:?}", case, String::from_utf8_lossy(&contents), terminator);
+            }
+        }
+    }
+}
@@ -0,0 +1,668 @@
+//! Destinations for entries besides a logger's own file.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, Write};
+use std::net::{TcpStream, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::{UnixDatagram, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::error::IoContext;
+use crate::reader::{parse_line, LogEntry};
+use crate::syslog::{entry_to_syslog, SyslogOptions};
+
+/// Put at the end of a datagram that `SyslogUdpSink` had to shorten.
+const TRUNCATED: &str = "...[truncated]";
+
+/// A destination for entries, for plugging in a transport of your own
+/// next to or instead of the log file. See `Logger::also_sink` and
+/// `Logger::to_sinks`. `StdoutSink`, `RingSink`, `MemorySink`,
+/// `SyslogUdpSink` and `FileSink` all implement it, so they can be combined
+/// with custom sinks.
+///
+/// Sinks are called one at a time, under the logger's write lock, in the
+/// order they were added.
+pub trait Sink: fmt::Debug + Send {
+    /// Writes one entry. `entry` displays as the line a log file would
+    /// get, without its terminator.
+    fn write_entry(&mut self, entry: &LogEntry) -> Result<(), Box<dyn Error>>;
+
+    /// Pushes out anything the sink holds back, see `Logger::flush_all`.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
+
+/// Appends entries to a log, the way `errlog` does, for combining a file
+/// with other sinks in `Logger::to_sinks`. It takes no locks and keeps no
+/// checksum chain; a `Logger` over its own file does both.
+#[derive(Debug, Clone)]
+pub struct FileSink {
+    path: PathBuf,
+}
+
+impl FileSink {
+    /// A sink appending to `file_path`, which goes through the same
+    /// validation as `errlog`. The file is only made on the first entry.
+    pub fn new(file_path: &str) -> Result<FileSink, Box<dyn Error>> {
+        Ok(FileSink { path: crate::create_path_from_str(file_path)? })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Sink for FileSink {
+    fn write_entry(&mut self, entry: &LogEntry) -> Result<(), Box<dyn Error>> {
+        crate::check_or_make_directory(&self.path)?;
+        crate::check_or_make_log(&self.path)?;
+        crate::append_line(&self.path, &entry.to_string(), b"\n")
+    }
+}
+
+/// Sends entries to standard output instead of a file, for platforms that
+/// collect a process's stdout. Entries are formatted exactly like file
+/// entries, minus the blank line the file layout puts in front of them.
+pub struct StdoutSink {
+    writer: Box<dyn Write + Send>,
+    journald: bool,
+}
+
+impl StdoutSink {
+    pub fn new() -> StdoutSink {
+        StdoutSink::from_writer(io::stdout())
+    }
+
+    /// A sink that writes to `writer` in place of stdout, mostly useful for
+    /// capturing output in tests.
+    pub fn from_writer(writer: impl Write + Send + 'static) -> StdoutSink {
+        StdoutSink { writer: Box::new(writer), journald: false }
+    }
+
+    /// A sink that writes to stderr, e.g. for a service whose stderr is
+    /// captured by systemd.
+    pub fn stderr() -> StdoutSink {
+        StdoutSink::from_writer(io::stderr())
+    }
+
+    /// Starts every entry with the `<3>` priority prefix journald reads
+    /// from a captured stream, so entries show up at error priority. Every
+    /// entry in this crate is an error, so the prefix is always `<3>`.
+    ///
+    /// journald treats each physical line as its own message, so line
+    /// breaks inside a message are written as the two characters `\n` (and
+    /// `\r`) instead of as continuation lines, keeping the entry in one
+    /// journal record: `<3>200 - first\nsecond`. A log file is never
+    /// written this way; this only changes what the sink prints.
+    pub fn with_journald_prefix(mut self, journald: bool) -> StdoutSink {
+        self.journald = journald;
+        self
+    }
+
+    /// Writes `line` and `terminator` with a single `write_all`, then
+    /// flushes so the collector sees it right away.
+    pub(crate) fn write_record(&mut self, line: &str, terminator: &[u8]) -> io::Result<()> {
+        let line = match (self.journald, parse_line(line)) {
+            (false, _) => Cow::Borrowed(line),
+            (true, Some(entry)) => Cow::Owned(format!("<3>{}", single_line(&entry))),
+            (true, None) => Cow::Owned(format!("<3>{}", line.replace('\r', "\\r").replace('\n', "\\n"))),
+        };
+        let mut record = Vec::with_capacity(line.len() + terminator.len());
+        record.extend_from_slice(line.as_bytes());
+        record.extend_from_slice(terminator);
+
+        self.writer.write_all(&record)?;
+        self.writer.flush()
+    }
+}
+
+/// `entry` as one physical line, with the line breaks in its message
+/// written as the two characters `\n` or `\r` rather than as continuation
+/// lines.
+pub(crate) fn single_line(entry: &LogEntry) -> String {
+    if !entry.message.contains(['\n', '\r']) {
+        return entry.to_string();
+    }
+    let message = entry.message.replace('\r', "\\r").replace('\n', "\\n");
+    LogEntry { message, ..entry.clone() }.to_string()
+}
+
+impl Sink for StdoutSink {
+    fn write_entry(&mut self, entry: &LogEntry) -> Result<(), Box<dyn Error>> {
+        Ok(self.write_record(&entry.to_string(), b"\n")?)
+    }
+
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+impl Default for StdoutSink {
+    fn default() -> StdoutSink {
+        StdoutSink::new()
+    }
+}
+
+impl fmt::Debug for StdoutSink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StdoutSink").finish_non_exhaustive()
+    }
+}
+
+/// Keeps the last `capacity` entries in memory instead of writing them
+/// anywhere, until `dump_to` writes them out, e.g. for a crash dump once
+/// something goes wrong. See `Logger::to_ring_sink`.
+///
+/// Clones share the same entries, so keep one to dump from after handing
+/// the sink to a logger.
+#[derive(Debug, Clone)]
+pub struct RingSink {
+    capacity: usize,
+    lines: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl RingSink {
+    /// # Panics
+    /// If `capacity` is 0.
+    pub fn new(capacity: usize) -> RingSink {
+        assert!(capacity > 0, "capacity must not be 0");
+        RingSink { capacity, lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))) }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How many entries are held right now, at most `capacity`.
+    pub fn len(&self) -> usize {
+        self.held().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held().is_empty()
+    }
+
+    /// The entries held, oldest first, formatted as they would be in a file.
+    pub fn lines(&self) -> Vec<String> {
+        self.held().iter().cloned().collect()
+    }
+
+    /// Appends the entries held to the log at `file_path`, oldest first and
+    /// in one write, then empties the sink. `file_path` goes through the
+    /// same validation as `errlog`. Records are newline terminated whatever
+    /// the logger's terminator. Returns how many entries were written.
+    pub fn dump_to(&self, file_path: &str) -> Result<usize, Box<dyn Error>> {
+        let path = crate::create_path_from_str(file_path)?;
+        crate::check_or_make_directory(&path)?;
+        crate::check_or_make_log(&path)?;
+
+        let mut held = self.held();
+        let contents: Vec<u8> = held.iter().flat_map(|line| crate::record(line, b"\n")).collect();
+        let mut file = fs::OpenOptions::new().append(true).open(&path).map_err(IoContext::wrap("open", &path))?;
+        file.write_all(&contents).map_err(IoContext::wrap("append to", &path))?;
+
+        let written = held.len();
+        held.clear();
+        Ok(written)
+    }
+
+    /// Adds `line`, forgetting the oldest entry when the sink is full.
+    pub(crate) fn push(&self, line: String) {
+        let mut held = self.held();
+        if held.len() == self.capacity {
+            held.pop_front();
+        }
+        held.push_back(line);
+    }
+
+    fn held(&self) -> MutexGuard<'_, VecDeque<String>> {
+        self.lines.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Sink for RingSink {
+    fn write_entry(&mut self, entry: &LogEntry) -> Result<(), Box<dyn Error>> {
+        self.push(entry.to_string());
+        Ok(())
+    }
+}
+
+/// Keeps every entry in memory so tests of code that logs can look at what
+/// it wrote without a file. See `Logger::to_memory_sink`.
+///
+/// Clones share the same entries, so keep one to check after handing the
+/// sink to a logger.
+#[derive(Debug, Clone, Default)]
+pub struct MemorySink {
+    entries: Arc<Mutex<Vec<LogEntry>>>,
+}
+
+impl MemorySink {
+    pub fn new() -> MemorySink {
+        MemorySink::default()
+    }
+
+    /// The entries written so far, oldest first.
+    pub fn entries(&self) -> Vec<LogEntry> {
+        self.held().clone()
+    }
+
+    /// The entries written so far, oldest first, formatted as they would
+    /// be in a file.
+    pub fn lines(&self) -> Vec<String> {
+        self.held().iter().map(|entry| entry.to_string()).collect()
+    }
+
+    /// Whether any entry's line contains `text`.
+    pub fn contains(&self, text: &str) -> bool {
+        self.held().iter().any(|entry| entry.to_string().contains(text))
+    }
+
+    pub fn len(&self) -> usize {
+        self.held().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.held().is_empty()
+    }
+
+    /// Forgets every entry written so far.
+    pub fn clear(&self) {
+        self.held().clear();
+    }
+
+    fn held(&self) -> MutexGuard<'_, Vec<LogEntry>> {
+        self.entries.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Sink for MemorySink {
+    fn write_entry(&mut self, entry: &LogEntry) -> Result<(), Box<dyn Error>> {
+        self.held().push(entry.clone());
+        Ok(())
+    }
+}
+
+/// Sends a copy of every entry to a syslog collector as an RFC 5424 line,
+/// one UDP datagram each, next to the logger's own output. See
+/// `Logger::also_syslog_udp`.
+///
+/// Sending never waits and never fails a write: the socket doesn't block,
+/// and a datagram that can't be sent is only counted in `failed_sends`.
+#[derive(Debug)]
+pub struct SyslogUdpSink {
+    socket: UdpSocket,
+    options: SyslogOptions,
+    max_datagram: usize,
+    failed: AtomicU64,
+}
+
+impl SyslogUdpSink {
+    /// Opens the socket for sending to `addr`, e.g. `"10.0.0.9:514"`, with
+    /// header fields from `options`. The socket is made here once and kept.
+    /// Datagrams are limited to 2048 bytes, see `with_max_datagram`.
+    pub fn connect(addr: &str, options: SyslogOptions) -> io::Result<SyslogUdpSink> {
+        let target = addr
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("no address for {}", addr)))?;
+        let socket = UdpSocket::bind(if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })?;
+        socket.connect(target)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(SyslogUdpSink { socket, options, max_datagram: 2048, failed: AtomicU64::new(0) })
+    }
+
+    /// Cuts longer lines down to `max` bytes, at a character boundary and
+    /// ending in `...[truncated]` unless `max` is too small to hold it.
+    pub fn with_max_datagram(mut self, max: usize) -> SyslogUdpSink {
+        self.max_datagram = max;
+        self
+    }
+
+    /// Datagrams that couldn't be sent so far.
+    pub fn failed_sends(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn send(&self, entry: &LogEntry) {
+        let line = entry_to_syslog(entry, &self.options);
+        let datagram = truncate(&line, self.max_datagram);
+        if self.socket.send(datagram.as_bytes()).is_err() {
+            self.failed.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Never fails: a datagram that can't be sent is counted in `failed_sends`.
+impl Sink for SyslogUdpSink {
+    fn write_entry(&mut self, entry: &LogEntry) -> Result<(), Box<dyn Error>> {
+        self.send(entry);
+        Ok(())
+    }
+}
+
+/// Sends every entry to a collector over TCP as one line ending in `\n`.
+/// Line breaks inside a message are written as the two characters `\n`
+/// (and `\r`) instead of as continuation lines, so each entry stays one
+/// line. See `Logger::also_sink`.
+///
+/// The connection is made on the first entry and made again after it
+/// breaks, from the logging thread. Reconnecting waits out a backoff that
+/// doubles from 10ms up to `with_max_backoff` after every failed attempt.
+/// While there's no connection, entries wait in a buffer of up to
+/// `with_buffer` entries and go out in order once there is one; entries
+/// that don't fit are dropped and counted. An entry whose write failed is
+/// kept for the next connection, so the collector sees it at least once,
+/// while one that was written before the break was noticed can be lost.
+/// An entry that fails even on a fresh connection is dropped and counted
+/// too, so one that can never go out doesn't hold up the rest.
+///
+/// Writing never fails and its stalls are bounded by `with_write_timeout`,
+/// so a collector that's down never holds up the logger's file.
+#[derive(Debug)]
+pub struct TcpSink {
+    addr: String,
+    write_timeout: Duration,
+    redial: Redial<TcpStream>,
+}
+
+impl TcpSink {
+    /// A sink for the collector at `addr`, e.g. `"10.0.0.9:5170"`, which is
+    /// looked up on every connection attempt. Buffers up to 1000 entries,
+    /// with a one second write timeout and backoff capped at 5 seconds.
+    pub fn new(addr: &str) -> TcpSink {
+        TcpSink { addr: addr.to_owned(), write_timeout: Duration::from_secs(1), redial: Redial::new() }
+    }
+
+    /// Holds at most `max` entries while disconnected.
+    pub fn with_buffer(mut self, max: usize) -> TcpSink {
+        self.redial.max_pending = max;
+        self
+    }
+
+    /// Longest a connection attempt or a single write may take.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> TcpSink {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Longest wait between reconnection attempts.
+    pub fn with_max_backoff(mut self, max: Duration) -> TcpSink {
+        self.redial.max_backoff = max;
+        self
+    }
+
+    /// Entries dropped because the buffer was full or they couldn't be
+    /// sent at all. The count is shared, so keep this before handing the
+    /// sink to a logger.
+    pub fn dropped_counter(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.redial.dropped)
+    }
+
+    fn drain(&mut self) {
+        let (addr, timeout) = (&self.addr, self.write_timeout);
+        let connect = || {
+            let mut last = io::Error::new(io::ErrorKind::InvalidInput, format!("no address for {}", addr));
+            for addr in addr.to_socket_addrs()? {
+                match TcpStream::connect_timeout(&addr, timeout) {
+                    Ok(stream) => {
+                        stream.set_write_timeout(Some(timeout))?;
+                        return Ok(stream);
+                    }
+                    Err(e) => last = e,
+                }
+            }
+            Err(last)
+        };
+        self.redial.drain(connect, |stream, line| stream.write_all(line));
+    }
+}
+
+impl Sink for TcpSink {
+    fn write_entry(&mut self, entry: &LogEntry) -> Result<(), Box<dyn Error>> {
+        self.redial.push(entry);
+        self.drain();
+        Ok(())
+    }
+
+    /// Tries once more to send what's buffered. Never fails either.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.drain();
+        Ok(())
+    }
+}
+
+/// Whether a `UnixSink` connects a stream or sends datagrams.
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnixSocketKind {
+    /// `SOCK_STREAM`, through `UnixStream`.
+    Stream,
+    /// `SOCK_DGRAM`, through `UnixDatagram`, one datagram per entry.
+    Datagram,
+}
+
+/// Sends every entry to a collector listening on a Unix domain socket,
+/// e.g. `/run/collector.sock`, framed, buffered and reconnected exactly
+/// like `TcpSink`. With `UnixSocketKind::Datagram` every line, still
+/// ending in `\n`, is a datagram of its own.
+#[cfg(unix)]
+#[derive(Debug)]
+pub struct UnixSink {
+    path: PathBuf,
+    kind: UnixSocketKind,
+    write_timeout: Duration,
+    redial: Redial<UnixConnection>,
+}
+
+#[cfg(unix)]
+#[derive(Debug)]
+enum UnixConnection {
+    Stream(UnixStream),
+    Datagram(UnixDatagram),
+}
+
+#[cfg(unix)]
+impl UnixSink {
+    /// A sink for the socket at `path`. Fails with `SocketNotFoundError`
+    /// if there's nothing at `path` yet; once made, the socket going away
+    /// only means reconnecting. Buffers up to 1000 entries, with a one
+    /// second write timeout and backoff capped at 5 seconds.
+    pub fn new(path: impl AsRef<Path>, kind: UnixSocketKind) -> Result<UnixSink, Box<dyn Error>> {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Err(Box::new(crate::error::SocketNotFoundError { message: format!("no socket at {}", path.display()) }));
+        }
+        Ok(UnixSink { path: path.to_path_buf(), kind, write_timeout: Duration::from_secs(1), redial: Redial::new() })
+    }
+
+    /// Holds at most `max` entries while disconnected.
+    pub fn with_buffer(mut self, max: usize) -> UnixSink {
+        self.redial.max_pending = max;
+        self
+    }
+
+    /// Longest a single write may take.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> UnixSink {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Longest wait between reconnection attempts.
+    pub fn with_max_backoff(mut self, max: Duration) -> UnixSink {
+        self.redial.max_backoff = max;
+        self
+    }
+
+    /// Entries dropped, see `TcpSink::dropped_counter`.
+    pub fn dropped_counter(&self) -> Arc<AtomicU64> {
+        Arc::clone(&self.redial.dropped)
+    }
+
+    fn drain(&mut self) {
+        let (path, timeout) = (&self.path, self.write_timeout);
+        let connect = || match self.kind {
+            UnixSocketKind::Datagram => {
+                let socket = UnixDatagram::unbound()?;
+                socket.connect(path)?;
+                socket.set_write_timeout(Some(timeout))?;
+                Ok(UnixConnection::Datagram(socket))
+            }
+            UnixSocketKind::Stream => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_write_timeout(Some(timeout))?;
+                Ok(UnixConnection::Stream(stream))
+            }
+        };
+        self.redial.drain(connect, |connection, line| match connection {
+            UnixConnection::Stream(stream) => stream.write_all(line),
+            UnixConnection::Datagram(socket) => socket.send(line).map(|_| ()),
+        });
+    }
+}
+
+#[cfg(unix)]
+impl Sink for UnixSink {
+    fn write_entry(&mut self, entry: &LogEntry) -> Result<(), Box<dyn Error>> {
+        self.redial.push(entry);
+        self.drain();
+        Ok(())
+    }
+
+    /// Tries once more to send what's buffered. Never fails either.
+    fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.drain();
+        Ok(())
+    }
+}
+
+/// The buffer and reconnection backoff `TcpSink` and `UnixSink` share,
+/// over a connection of type `C`.
+#[derive(Debug)]
+struct Redial<C> {
+    connection: Option<C>,
+    pending: VecDeque<String>,
+    max_pending: usize,
+    backoff: Duration,
+    max_backoff: Duration,
+    next_attempt: Option<Instant>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl<C> Redial<C> {
+    /// First wait between reconnection attempts.
+    const FIRST_BACKOFF: Duration = Duration::from_millis(10);
+
+    fn new() -> Redial<C> {
+        Redial {
+            connection: None,
+            pending: VecDeque::new(),
+            max_pending: 1000,
+            backoff: Redial::<C>::FIRST_BACKOFF,
+            max_backoff: Duration::from_secs(5),
+            next_attempt: None,
+            dropped: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Queues `entry` as one `\n`-terminated line, or counts it as dropped
+    /// when the buffer is full.
+    fn push(&mut self, entry: &LogEntry) {
+        if self.pending.len() >= self.max_pending {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.pending.push_back(format!("{}\n", single_line(entry)));
+        }
+    }
+
+    /// Sends as much of `pending` as the connection takes, calling
+    /// `connect` at most once and only when the backoff has run out.
+    fn drain(&mut self, connect: impl FnOnce() -> io::Result<C>, send: impl Fn(&mut C, &[u8]) -> io::Result<()>) {
+        let mut connect = Some(connect);
+        let mut fresh = false;
+        while let Some(line) = self.pending.front() {
+            let connection = match &mut self.connection {
+                Some(connection) => connection,
+                None => {
+                    let waiting = self.next_attempt.is_some_and(|next| Instant::now() < next);
+                    let Some(connect) = connect.take().filter(|_| !waiting) else {
+                        return;
+                    };
+                    match connect() {
+                        Ok(connection) => {
+                            self.backoff = Redial::<C>::FIRST_BACKOFF;
+                            self.next_attempt = None;
+                            fresh = true;
+                            self.connection.insert(connection)
+                        }
+                        Err(_) => {
+                            self.next_attempt = Some(Instant::now() + self.backoff);
+                            self.backoff = (self.backoff * 2).min(self.max_backoff);
+                            return;
+                        }
+                    }
+                }
+            };
+
+            match send(connection, line.as_bytes()) {
+                Ok(()) => {
+                    self.pending.pop_front();
+                }
+                Err(_) if fresh => {
+                    self.pending.pop_front();
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    self.connection = None;
+                    return;
+                }
+                Err(_) => self.connection = None,
+            }
+            fresh = false;
+        }
+    }
+}
+
+/// `line` cut to at most `max` bytes including the `TRUNCATED` marker, or
+/// without it when `max` is too small to hold the marker.
+fn truncate(line: &str, max: usize) -> Cow<'_, str> {
+    if line.len() <= max {
+        return Cow::Borrowed(line);
+    }
+    let marker = if max < TRUNCATED.len() { "" } else { TRUNCATED };
+    let mut end = max - marker.len();
+    while !line.is_char_boundary(end) {
+        end -= 1;
+    }
+    Cow::Owned(format!("{}{}", &line[..end], marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_never_exceeds_max() {
+        assert_eq!("short", truncate("short", 5));
+
+        // the snowman's three bytes straddle the cut, so all of it goes
+        let line = "abc☃ and then more than the marker holds";
+        let max = 4 + TRUNCATED.len();
+        assert_eq!(format!("abc{}", TRUNCATED), truncate(line, max));
+        assert_eq!(format!("abc☃{}", TRUNCATED), truncate(line, max + 2));
+
+        // too small for the marker
+        for max in 0..TRUNCATED.len() {
+            let cut = truncate("a☃ long enough line", max);
+            assert!(cut.len() <= max, "{:?} is longer than {}", cut, max);
+        }
+        assert_eq!("a", truncate("a☃ long enough line", 3));
+    }
+}
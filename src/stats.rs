@@ -0,0 +1,266 @@
+//! Summary numbers over a whole log, computed in a single streaming pass.
+
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crate::date::Date;
+use crate::reader::{entries, Record};
+
+/// Number of messages listed in a `write_report` report.
+const REPORT_TOP_MESSAGES: usize = 10;
+
+/// Counts gathered by `stats`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogStats {
+    /// Number of lines that parsed as entries.
+    pub total: u64,
+    /// Number of non-blank lines that didn't parse.
+    pub malformed: u64,
+    /// Earliest entry timestamp, in seconds since `UNIX_EPOCH`.
+    pub first: Option<u64>,
+    /// Latest entry timestamp, in seconds since `UNIX_EPOCH`.
+    pub last: Option<u64>,
+    /// Entries per UTC day, oldest day first.
+    pub per_day: Vec<(Date, u64)>,
+}
+
+/// Streams the log at `path` and counts its entries per UTC day.
+pub fn stats(path: &str) -> Result<LogStats, Box<dyn Error>> {
+    Ok(tally(path, None)?.0)
+}
+
+/// One pass over the log at `path` for both `stats` and `top_messages`.
+/// Messages are only counted when `fuzzy` is given.
+fn tally(path: &str, fuzzy: Option<bool>) -> Result<(LogStats, HashMap<String, u64>), Box<dyn Error>> {
+    let mut stats = LogStats::default();
+    let mut per_day = BTreeMap::new();
+    let mut counts: HashMap<String, u64> = HashMap::new();
+
+    for record in entries(path)? {
+        match record? {
+            Record::Entry(entry) => {
+                stats.total += 1;
+                stats.first = Some(stats.first.map_or(entry.timestamp, |t| t.min(entry.timestamp)));
+                stats.last = Some(stats.last.map_or(entry.timestamp, |t| t.max(entry.timestamp)));
+                *per_day.entry(Date::from_timestamp(entry.timestamp)).or_insert(0) += 1;
+
+                if let Some(fuzzy) = fuzzy {
+                    let message = entry.message.trim();
+                    let key = if fuzzy { collapse_digits(message) } else { message.to_owned() };
+                    *counts.entry(key).or_insert(0) += 1;
+                }
+            }
+            Record::Malformed { .. } => stats.malformed += 1,
+        }
+    }
+
+    stats.per_day = per_day.into_iter().collect();
+    Ok((stats, counts))
+}
+
+/// The `n` most common messages in the log at `path` with how often each
+/// occurs, most common first and ties in alphabetical order. Messages are
+/// trimmed before counting; with `fuzzy` every run of digits is also
+/// replaced by `#`, so `timeout after 5012ms` and `timeout after 4998ms`
+/// both count as `timeout after #ms`.
+pub fn top_messages(path: &str, n: usize, fuzzy: bool) -> Result<Vec<(String, u64)>, Box<dyn Error>> {
+    let (_, counts) = tally(path, Some(fuzzy))?;
+    Ok(rank(counts, n))
+}
+
+fn rank(counts: HashMap<String, u64>, n: usize) -> Vec<(String, u64)> {
+    let mut counts: Vec<(String, u64)> = counts.into_iter().collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts.truncate(n);
+    counts
+}
+
+/// Writes a plain text summary of the log at `log_path`: the `stats`
+/// table followed by the ten most common messages. Without `report_path`
+/// it goes next to the log, `errors.log` getting `errors.report.txt`.
+/// Sections always come in the same order so reports from different runs
+/// can be diffed. The log is read once and the report written atomically.
+pub fn write_report(log_path: &str, report_path: Option<&str>) -> Result<PathBuf, Box<dyn Error>> {
+    let log = crate::create_path_from_str(log_path)?;
+    let report_path = match report_path {
+        Some(path) => PathBuf::from(path),
+        None => log.with_extension("report.txt"),
+    };
+
+    let (stats, counts) = tally(log_path, Some(false))?;
+    let mut report = String::new();
+    writeln!(report, "errlog report for {}", log.display())?;
+    writeln!(report)?;
+    write!(report, "{}", stats)?;
+    writeln!(report)?;
+    writeln!(report, "{:>8}  top messages", "count")?;
+    for (message, count) in rank(counts, REPORT_TOP_MESSAGES) {
+        writeln!(report, "{:>8}  {}", count, message.replace('\r', "\\r").replace('\n', "\\n"))?;
+    }
+
+    crate::atomic::write_atomically(Path::new(&report_path), report.as_bytes())?;
+    Ok(report_path)
+}
+
+fn collapse_digits(message: &str) -> String {
+    let mut collapsed = String::with_capacity(message.len());
+    let mut in_digits = false;
+    for c in message.chars() {
+        if c.is_ascii_digit() {
+            if !in_digits {
+                collapsed.push('#');
+            }
+            in_digits = true;
+        } else {
+            collapsed.push(c);
+            in_digits = false;
+        }
+    }
+    collapsed
+}
+
+impl fmt::Display for LogStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "entries:    {}", self.total)?;
+        writeln!(f, "malformed:  {}", self.malformed)?;
+        if let (Some(first), Some(last)) = (self.first, self.last) {
+            writeln!(f, "first:      {} ({})", first, Date::from_timestamp(first))?;
+            writeln!(f, "last:       {} ({})", last, Date::from_timestamp(last))?;
+        }
+
+        writeln!(f)?;
+        writeln!(f, "{:<12}{:>8}", "day", "entries")?;
+        for (date, count) in &self.per_day {
+            writeln!(f, "{:<12}{:>8}", date.to_string(), count)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::TempLogDir;
+    use std::fs;
+
+    // 2024-06-01 00:00:00 UTC
+    const DAY_ONE: u64 = 1_717_200_000;
+
+    #[test]
+    fn stats_over_three_days() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("stats-test.log");
+        let log = format!(
+            "\n{} - a\n\n{} - b\nnot an entry\n{} - c\n{} - d\n{} - e\n{} - f\n",
+            DAY_ONE,
+            DAY_ONE + 86_399,
+            DAY_ONE + 86_400,
+            DAY_ONE + 90_000,
+            DAY_ONE + 100_000,
+            DAY_ONE + 2 * 86_400 + 5,
+        );
+        fs::write(path, log).unwrap();
+
+        let stats = stats(path).unwrap();
+        assert_eq!(6, stats.total);
+        assert_eq!(1, stats.malformed);
+        assert_eq!(Some(DAY_ONE), stats.first);
+        assert_eq!(Some(DAY_ONE + 2 * 86_400 + 5), stats.last);
+        assert_eq!(
+            vec![
+                (Date { year: 2024, month: 6, day: 1 }, 2),
+                (Date { year: 2024, month: 6, day: 2 }, 3),
+                (Date { year: 2024, month: 6, day: 3 }, 1),
+            ],
+            stats.per_day
+        );
+
+        let table = stats.to_string();
+        assert!(table.contains("entries:    6\n"));
+        assert!(table.contains("malformed:  1\n"));
+        assert!(table.contains("2024-06-02         3\n"));
+    }
+
+    #[test]
+    fn report_for_known_log() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("stats-report-test.log");
+        let log = format!(
+            "\n{} - disk full\n\n{} - timeout\nnot an entry\n\n{} - disk full\n\n{} - two\n\t| lines\n",
+            DAY_ONE,
+            DAY_ONE + 60,
+            DAY_ONE + 86_400,
+            DAY_ONE + 86_401,
+        );
+        fs::write(path, log).unwrap();
+
+        let report_path = write_report(path, None).unwrap();
+        assert_eq!(dir.path().join("stats-report-test.report.txt"), report_path);
+        assert_eq!(
+            format!(
+                "errlog report for {}\n\
+             \n\
+             entries:    4\n\
+             malformed:  1\n\
+             first:      1717200000 (2024-06-01)\n\
+             last:       1717286401 (2024-06-02)\n\
+             \n\
+             day          entries\n\
+             2024-06-01         2\n\
+             2024-06-02         2\n\
+             \n   \
+             count  top messages\n       \
+             2  disk full\n       \
+             1  timeout\n       \
+             1  two\\nlines\n",
+                path
+            ),
+            fs::read_to_string(&report_path).unwrap()
+        );
+
+        let custom = write_report(path, Some(&dir.file("stats-custom-report.txt"))).unwrap();
+        assert_eq!(fs::read(&report_path).unwrap(), fs::read(&custom).unwrap());
+    }
+
+    #[test]
+    fn top_messages_exact_and_fuzzy() {
+        let dir = TempLogDir::new();
+        let path = &dir.file("top-messages-test.log");
+        let log = [
+            "1 - timeout after 5012ms",
+            "2 - timeout after 4998ms",
+            "3 - disk full",
+            "4 -   disk full  ",
+            "5 - bad request 400",
+            "6 - bad request 404",
+            "7 - bad request 404",
+            "8 - auth failed",
+            "9 - zebra",
+            "not an entry",
+        ];
+        fs::write(path, format!("\n{}\n", log.join("\n\n"))).unwrap();
+
+        assert_eq!(
+            vec![
+                (String::from("bad request 404"), 2),
+                (String::from("disk full"), 2),
+                (String::from("auth failed"), 1),
+                (String::from("bad request 400"), 1),
+            ],
+            top_messages(path, 4, false).unwrap()
+        );
+        assert_eq!(
+            vec![
+                (String::from("bad request #"), 3),
+                (String::from("disk full"), 2),
+                (String::from("timeout after #ms"), 2),
+                (String::from("auth failed"), 1),
+                (String::from("zebra"), 1),
+            ],
+            top_messages(path, 10, true).unwrap()
+        );
+        assert!(top_messages(path, 0, true).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+//! RFC 5424 syslog lines, for forwarding logs with rsyslog and the like.
+
+use crate::date::rfc3339_fixed;
+use crate::reader::LogEntry;
+
+/// Every entry is an error, severity 3 in RFC 5424.
+const SEVERITY_ERROR: u8 = 3;
+
+/// Header fields for `LogEntry::to_syslog` and `export_syslog`. Fields left
+/// `None` are written as `-`, the RFC's nil value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyslogOptions {
+    /// 0 to 23, `user` (1) by default. The priority is
+    /// `facility * 8 + severity`.
+    pub facility: u8,
+    pub hostname: Option<String>,
+    pub app_name: Option<String>,
+    pub procid: Option<String>,
+}
+
+impl Default for SyslogOptions {
+    fn default() -> SyslogOptions {
+        SyslogOptions {
+            facility: 1,
+            hostname: None,
+            app_name: None,
+            procid: None,
+        }
+    }
+}
+
+/// Appends a header field as RFC 5424 allows it: printable ASCII without
+/// spaces and at most `max` characters. Anything else becomes `_`, and an
+/// absent or empty field becomes `-`.
+fn push_field(out: &mut String, field: Option<&str>, max: usize) {
+    out.push(' ');
+    match field.filter(|field| !field.is_empty()) {
+        Some(field) => out.extend(field.chars().take(max).map(|c| if c.is_ascii_graphic() { c } else { '_' })),
+        None => out.push('-'),
+    }
+}
+
+/// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID - MSG`, with a coded
+/// entry's code as the MSGID, e.g. `E0042`. Line breaks in the message
+/// become continuation lines, as in the plain format, so one entry never
+/// spreads over several syslog lines.
+pub(crate) fn entry_to_syslog(entry: &LogEntry, options: &SyslogOptions) -> String {
+    let msgid = entry.code.map(|code| format!("E{:0width$}", code, width = crate::DEFAULT_CODE_WIDTH));
+    let message = crate::reader::encode_message(&entry.message).replace("\n\t", " ");
+    line(options, rfc3339_fixed(entry.timestamp, entry.millis).as_str(), msgid.as_deref(), &message)
+}
+
+/// A line that didn't parse as an entry, with a nil timestamp and the
+/// MSGID `malformed`.
+pub(crate) fn malformed_to_syslog(raw: &str, options: &SyslogOptions) -> String {
+    line(options, "-", Some("malformed"), raw)
+}
+
+fn line(options: &SyslogOptions, timestamp: &str, msgid: Option<&str>, message: &str) -> String {
+    let mut out = format!("<{}>1 {}", u32::from(options.facility.min(23)) * 8 + u32::from(SEVERITY_ERROR), timestamp);
+    push_field(&mut out, options.hostname.as_deref(), 255);
+    push_field(&mut out, options.app_name.as_deref(), 48);
+    push_field(&mut out, options.procid.as_deref(), 128);
+    push_field(&mut out, msgid, 32);
+    out.push_str(" - ");
+    out.push_str(message);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn entry(code: Option<u32>, message: &str) -> LogEntry {
+        LogEntry { timestamp: 1_709_820_543, millis: None, code, message: String::from(message), checksum: None, fields: BTreeMap::new() }
+    }
+
+    #[test]
+    fn syslog_with_and_without_header_fields() {
+        assert_eq!(
+            "<11>1 2024-03-07T14:09:03Z - - - - - connection refused",
+            entry(None, "connection refused").to_syslog(&SyslogOptions::default())
+        );
+
+        let options = SyslogOptions {
+            facility: 16,
+            hostname: Some(String::from("web-1")),
+            app_name: Some(String::from("my app")),
+            procid: Some(String::from("4242")),
+        };
+        assert_eq!(
+            "<131>1 2024-03-07T14:09:03Z web-1 my_app 4242 E0042 - disk full",
+            entry(Some(42), "disk full").to_syslog(&options)
+        );
+        assert_eq!("<131>1 - web-1 my_app 4242 malformed - torn", malformed_to_syslog("torn", &options));
+    }
+
+    #[test]
+    fn syslog_keeps_an_entry_on_one_line() {
+        let mut multi = entry(None, "first\nsecond\r\nthird ☃");
+        multi.millis = Some(5);
+        assert_eq!(
+            "<11>1 2024-03-07T14:09:03.005Z - - - - - first | second |r third ☃",
+            multi.to_syslog(&SyslogOptions::default())
+        );
+    }
+}
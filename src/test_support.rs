@@ -0,0 +1,103 @@
+//! Helpers for tests of code that logs, so tests running in parallel don't
+//! share files. Built for the crate's own tests and with the `test-util`
+//! feature.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// A fresh directory under `std::env::temp_dir()`, named after the pid, a
+/// counter and the time, and removed with everything in it when dropped,
+/// including when the test panics. Removal is best effort: a failure is
+/// ignored rather than turned into a second panic.
+#[derive(Debug)]
+pub struct TempLogDir {
+    path: PathBuf,
+}
+
+impl TempLogDir {
+    /// # Panics
+    /// If the directory can't be created.
+    pub fn new() -> TempLogDir {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |since| since.as_nanos());
+        let name = format!("errlog-test-{}-{}-{}", std::process::id(), NEXT_ID.fetch_add(1, Ordering::Relaxed), nanos);
+        let path = std::env::temp_dir().join(name);
+        if let Err(e) = fs::create_dir(&path) {
+            panic!("Could not create {}. Error: {e}", path.display());
+        }
+
+        TempLogDir { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// `name` inside the directory, as the `&str`-style path the API takes.
+    /// Nothing is created.
+    pub fn file(&self, name: &str) -> String {
+        self.path.join(name).to_string_lossy().into_owned()
+    }
+}
+
+impl Default for TempLogDir {
+    fn default() -> TempLogDir {
+        TempLogDir::new()
+    }
+}
+
+impl Drop for TempLogDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+    use std::thread;
+
+    #[test]
+    fn directories_are_unique_across_threads() {
+        let dirs: Vec<TempLogDir> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8).map(|_| scope.spawn(|| (0..16).map(|_| TempLogDir::new()).collect::<Vec<_>>())).collect();
+            handles.into_iter().flat_map(|handle| handle.join().unwrap()).collect()
+        });
+
+        let paths: HashSet<&Path> = dirs.iter().map(|dir| dir.path()).collect();
+        assert_eq!(128, paths.len());
+        assert!(dirs.iter().all(|dir| dir.path().is_dir()));
+    }
+
+    #[test]
+    fn removed_on_drop_even_after_a_panic() {
+        let dir = TempLogDir::new();
+        let path = dir.path().to_path_buf();
+        crate::errlog(&dir.file("nested.log"), String::from("gone soon")).unwrap();
+        drop(dir);
+        assert!(!path.exists());
+
+        let path = thread::spawn(|| {
+            let dir = TempLogDir::new();
+            crate::errlog(&dir.file("panicked.log"), String::from("gone too")).unwrap();
+            let path = dir.path().to_path_buf();
+            std::panic::panic_any(path);
+        })
+        .join()
+        .unwrap_err()
+        .downcast::<PathBuf>()
+        .unwrap();
+        assert!(!path.exists());
+
+        // a directory that's already gone is no reason to panic
+        let dir = TempLogDir::new();
+        fs::remove_dir(dir.path()).unwrap();
+        drop(dir);
+    }
+}
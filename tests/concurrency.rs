@@ -0,0 +1,60 @@
+use std::thread;
+
+use errlog::{Logger, Record};
+
+use errlog::test_support::TempLogDir;
+
+const THREADS: usize = 16;
+const PER_THREAD: usize = 2_000;
+
+/// Padding for entry `i` of a thread: mostly short, every 97th a few KB.
+fn padding(i: usize) -> usize {
+    if i.is_multiple_of(97) {
+        4 * 1024 + i % 1024
+    } else {
+        i * 37 % 200
+    }
+}
+
+#[test]
+fn every_entry_lands_whole_and_once() {
+    let dir = TempLogDir::new();
+    let path = dir.file("stress.log");
+    let logger = Logger::new(&path).unwrap();
+
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let logger = logger.clone();
+            scope.spawn(move || {
+                for i in 0..PER_THREAD {
+                    let fill = char::from(b'a' + (t % 26) as u8).to_string().repeat(padding(i));
+                    logger.log(format!("t{} e{} {}", t, i, fill)).unwrap();
+                }
+            });
+        }
+    });
+
+    let mut seen = vec![vec![false; PER_THREAD]; THREADS];
+    for record in errlog::entries(&path).unwrap() {
+        let entry = match record.unwrap() {
+            Record::Entry(entry) => entry,
+            Record::Malformed { line, raw } => panic!("Line {} is corrupt: {:?}", line, raw),
+        };
+
+        let mut fields = entry.message.splitn(3, ' ');
+        let t: usize = fields.next().and_then(|f| f.strip_prefix('t')).and_then(|f| f.parse().ok()).expect(&entry.message);
+        let i: usize = fields.next().and_then(|f| f.strip_prefix('e')).and_then(|f| f.parse().ok()).expect(&entry.message);
+        let fill = fields.next().unwrap_or_default();
+        let fill_char = char::from(b'a' + (t % 26) as u8);
+        assert!(fill.len() == padding(i) && fill.chars().all(|c| c == fill_char), "t{} e{} is garbled", t, i);
+
+        assert!(!seen[t][i], "t{} e{} was written twice", t, i);
+        seen[t][i] = true;
+    }
+
+    for (t, entries) in seen.iter().enumerate() {
+        if let Some(i) = entries.iter().position(|&found| !found) {
+            panic!("t{} e{} is missing", t, i);
+        }
+    }
+}
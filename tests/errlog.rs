@@ -0,0 +1,131 @@
+use std::fs;
+use std::panic::Location;
+use std::path::Path;
+
+use errlog::test_support::TempLogDir;
+
+#[test]
+fn errlog_success() {
+    let dir = TempLogDir::new();
+
+    let existing = dir.file("errlog-unit-test.log");
+    fs::write(&existing, "").unwrap();
+    if let Err(e) = errlog::errlog(&existing, String::from("error log was successful")) {
+        panic!("Something went horribly wrong. Error: {e}");
+    }
+
+    let in_new_folder = dir.file("new-folder/errlog-unit-test.log");
+    if let Err(e) = errlog::errlog(&in_new_folder, String::from("error log was successful")) {
+        panic!("Something went horribly wrong. Error: {e}");
+    }
+
+    for path in [&existing, &in_new_folder] {
+        let found = errlog::read_entries(path).unwrap();
+        assert_eq!(1, found.len());
+        assert_eq!("error log was successful", found[0].message);
+    }
+}
+
+#[test]
+fn errlog_fail_bad_path() {
+    let dir = TempLogDir::new();
+
+    let nested = dir.file("no-folder/abcd/errlog-unit-test.log");
+    if errlog::errlog(&nested, String::from("error log should fail")).is_ok() {
+        panic!("`{nested}` should have failed as a bad path.");
+    }
+
+    let no_extension = dir.file("errlog-unit-test");
+    if errlog::errlog(&no_extension, String::from("error log should fail")).is_ok() {
+        panic!("not specifying a `.log` file should fail.");
+    }
+}
+
+#[test]
+fn errlog_raw_writes_the_line_unchanged() {
+    let dir = TempLogDir::new();
+    let path = dir.file("raw.log");
+
+    errlog::errlog_raw(&path, "2024-03-07T14:09:03.512+01:00 upstream said \"no\"").unwrap();
+    errlog::errlog(&path, String::from("stamped")).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    assert!(contents.starts_with("\n2024-03-07T14:09:03.512+01:00 upstream said \"no\"\n\n"));
+    assert!(contents.ends_with(" - stamped\n"));
+
+    if errlog::errlog_raw(&path, "two\nlines").is_ok() {
+        panic!("A raw line with a newline in it should have been refused.");
+    }
+    assert_eq!(contents, fs::read_to_string(&path).unwrap());
+}
+
+#[test]
+fn errlog_raw_newlines_are_refused_or_escaped() {
+    let dir = TempLogDir::new();
+    let path = dir.file("raw-newlines.log");
+
+    match errlog::errlog_raw_with(&path, "one\ntwo", errlog::RawNewlines::Reject) {
+        Err(e) => assert_eq!(Some(std::io::ErrorKind::InvalidInput), e.downcast_ref::<std::io::Error>().map(|e| e.kind())),
+        Ok(_) => panic!("A raw line with a newline in it should have been refused."),
+    }
+    assert!(!std::path::Path::new(&path).exists());
+
+    errlog::errlog_raw_with(&path, "one\ntwo", errlog::RawNewlines::Escape).unwrap();
+    assert_eq!("\none\\ntwo\n", fs::read_to_string(&path).unwrap());
+}
+
+#[test]
+fn errlog_returning_gives_back_the_written_line() {
+    let dir = TempLogDir::new();
+    let path = dir.file("returning.log");
+
+    errlog::errlog(&path, String::from("before")).unwrap();
+    let line = errlog::errlog_returning(&path, "disk \"full\"").unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    assert_eq!(Some(line.as_str()), contents.lines().last());
+    assert!(line.ends_with(" - disk \"full\""));
+
+    let line = errlog::errlog_returning(&path, "two\nlines").unwrap();
+    assert!(fs::read_to_string(&path).unwrap().ends_with(&format!("\n{}\n", line)));
+    assert_eq!("two\nlines", line.parse::<errlog::LogEntry>().unwrap().message);
+}
+
+#[test]
+fn format_entry_writes_nothing() {
+    let dir = TempLogDir::new();
+    let path = dir.file("formatted.log");
+
+    let formatted = errlog::format_entry("two\nlines");
+    assert!(!Path::new(&path).exists());
+    let written = errlog::errlog_returning(&path, "two\nlines").unwrap();
+    assert_eq!(written.split_once(" - ").unwrap().1, formatted.split_once(" - ").unwrap().1);
+    assert_eq!("two\nlines", formatted.parse::<errlog::LogEntry>().unwrap().message);
+}
+
+#[test]
+fn errlog_here_writes_the_callers_location() {
+    #[track_caller]
+    fn helper(path: &str) {
+        errlog::errlog_here(path, String::from("from a helper")).unwrap();
+    }
+
+    let dir = TempLogDir::new();
+    let path = dir.file("errlog-here.log");
+    let (here, _) = (Location::caller(), errlog::errlog_here(&path, String::from("direct")).unwrap());
+    let (through, _) = (Location::caller(), helper(&path));
+
+    let found = errlog::read_entries(&path).unwrap();
+    assert_eq!(format!("direct location={}:{}", file!(), here.line()), found[0].message);
+    assert_eq!(format!("from a helper location={}:{}", file!(), through.line()), found[1].message);
+    assert_eq!("tests/errlog.rs", here.file());
+}
+
+#[test]
+fn temp_dir_is_removed_on_drop() {
+    let dir = TempLogDir::new();
+    let path = dir.path().to_path_buf();
+    errlog::errlog(&dir.file("dropped.log"), String::from("gone soon")).unwrap();
+    assert!(path.is_dir());
+
+    drop(dir);
+    assert!(!path.exists());
+}
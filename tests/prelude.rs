@@ -0,0 +1,23 @@
+use errlog::prelude::*;
+
+use errlog::test_support::TempLogDir;
+
+#[test]
+fn prelude_covers_the_basics() {
+    let dir = TempLogDir::new();
+    let path = dir.file("prelude-test.log");
+
+    errlog(&path, String::from("plain")).unwrap();
+    errlog_coded(&path, 42, String::from("coded")).unwrap();
+
+    let logger = Logger::new(&path).unwrap();
+    logger.log(String::from("from logger")).unwrap();
+
+    let found: Vec<LogEntry> = read_entries(&path).unwrap();
+    assert_eq!(3, found.len());
+    assert_eq!(Some(42), found[1].code);
+    assert!(entries(&path).unwrap().all(|r| matches!(r.unwrap(), Record::Entry(_))));
+
+    let error = errlog(&dir.file("prelude-test.txt"), String::new()).unwrap_err();
+    assert!(error.downcast_ref::<BadExtensionError>().is_some());
+}